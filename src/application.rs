@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs::OpenOptions;
 use std::io::{BufReader, Cursor, ErrorKind, Read, Stdout, Write};
@@ -6,13 +6,16 @@ use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
-use std::{env, fs, io, path, process, vec};
+use std::{env, fs, io, path, process, thread, vec};
 
 use anyhow::{anyhow, Error, Result};
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use crossterm::cursor::{position, Hide};
 use crossterm::event::MouseButton::{Left, Middle, Right};
-use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::event::{
+    DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, KeyCode,
+    KeyEvent,
+};
 use crossterm::style::{self, ResetColor, Stylize};
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
@@ -20,22 +23,38 @@ use crossterm::terminal::{
 use crossterm::{cursor, execute, ExecutableCommand};
 use fs4::fs_std::FileExt;
 use log::{error, info, warn};
-use prost::Message;
+use prost::Message as _;
 use tui::backend::CrosstermBackend;
-use tui::layout::{Alignment, Constraint, Direction, Layout};
+use tui::layout::{Alignment, Constraint, Direction, Layout, Rect};
 use tui::style::{Color, Style};
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use tui::widgets::{self, Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
 use tui::Terminal;
-
-use crate::{dbgf, files, proto, Config, File};
+use unicode_width::UnicodeWidthStr;
+
+use crate::archive::{self, Member, Skipped};
+use crate::command_bar::{CommandBar, Message};
+use crate::config::{Action, Opener, ShellMode, Sort, SortKey};
+use crate::files::GitRanks;
+use crate::keymap::Keymap;
+use crate::output::{self, Output, Progress, Report};
+use crate::preview::Preview;
+use crate::session::{Session, LAST_SESSION};
+use crate::vfs::{self, Stat, Vfs};
+use crate::views::{View, Views};
+use crate::{check, commands, config, dbgf, files, opener, proto, Config, File};
 
 pub const SOCKET_PATH: &str = "/tmp/fm.sock";
 
+/// Maximum number of candidates visible at once in the completion menu.
+const COMPLETION_MENU_HEIGHT: u16 = 10;
+
 type CrossTerminal = Terminal<CrosstermBackend<io::Stdout>>;
 
 pub struct Application<'a> {
     pub terminal: &'a mut CrossTerminal,
+    /// Filesystem the tree and the preview are read from.
+    pub vfs: Arc<dyn Vfs>,
     pub files: File,
     pub copied: HashSet<PathBuf>,
     pub cut: HashSet<PathBuf>,
@@ -47,6 +66,45 @@ pub struct Application<'a> {
     pub command_bar: CommandBar,
     pub status: Status,
     pub updater: Sender<()>,
+    /// File the root directory is written to when quitting with `quit-cd`.
+    pub last_dir_path: Option<String>,
+    pub file_chooser: Option<FileChooser>,
+    pub keymap: Keymap,
+    /// User configuration file and the directory whose project configuration is loaded.
+    pub config_path: PathBuf,
+    pub config_root: PathBuf,
+    /// Expressions from `--override-config` which are applied again after a reload.
+    pub config_overrides: Vec<String>,
+    /// Output of the last captured shell command until it is dismissed.
+    pub output: Option<Output>,
+    /// Messages of background commands which have finished.
+    pub jobs: Receiver<Report>,
+    jobs_sender: Sender<Report>,
+    /// Action waiting for the user to confirm it with `y`, with its expanded command.
+    pub confirmation: Option<(String, Action)>,
+    /// Root and index of the selection when `hook_select` last ran.
+    last_selected: Option<(PathBuf, Option<usize>)>,
+    pub open_with: Option<OpenWith>,
+    pub preview: Preview,
+    /// Sort order, hidden files and expanded directories remembered per root.
+    pub views: Views,
+    /// Directory of the sessions saved with `session save <name>` and when quitting.
+    pub sessions_dir: PathBuf,
+}
+
+/// File chooser modes write the chosen paths to a file before quitting.
+#[derive(Clone, Debug)]
+pub enum FileChooser {
+    Dir(String),
+    Single(String),
+    Multiple(String),
+}
+
+/// The "Open with" menu listing the openers of a file.
+pub struct OpenWith {
+    pub path: PathBuf,
+    pub openers: Vec<Opener>,
+    pub state: ListState,
 }
 
 pub struct Status {
@@ -56,33 +114,21 @@ pub struct Status {
     pub git_modules: Arc<Mutex<HashSet<PathBuf>>>,
 }
 
-pub struct CommandBar {
-    pub command_entry_mode: bool,
-    pub prompt_text: String,
-    pub input_text: String,
-}
-
-impl CommandBar {
-    pub fn command_parameters(&self, command: &str) -> String {
-        if let Some(text) = self.input_text.strip_prefix(command) {
-            text.to_owned()
-        } else {
-            self.input_text.clone()
-        }
-    }
-}
-
 impl<'a> Application<'a> {
     pub fn new(
         terminal: &'a mut CrossTerminal,
+        vfs: Arc<dyn Vfs>,
         config: Config,
         root: File,
         sender: Sender<()>,
     ) -> Self {
         let mut state = ListState::default();
         state.select(Some(0));
+        let (jobs_sender, jobs) = mpsc::channel();
+        let preview = Preview::new(Arc::clone(&vfs));
         Application {
             terminal,
+            vfs,
             files: root,
             copied: HashSet::new(),
             cut: HashSet::new(),
@@ -91,11 +137,7 @@ impl<'a> Application<'a> {
             files_previous: PathBuf::new(),
             list_state: state,
             configuration: config,
-            command_bar: CommandBar {
-                command_entry_mode: false,
-                prompt_text: ":".into(),
-                input_text: String::default(),
-            },
+            command_bar: CommandBar::default(),
             status: Status {
                 git_status: Arc::new(Mutex::new(String::default())),
                 commit_count: Arc::new(Mutex::new(String::default())),
@@ -103,6 +145,21 @@ impl<'a> Application<'a> {
                 git_modules: Arc::new(Mutex::new(HashSet::new())),
             },
             updater: sender,
+            last_dir_path: None,
+            file_chooser: None,
+            keymap: Keymap::default(),
+            config_path: PathBuf::new(),
+            config_root: PathBuf::new(),
+            config_overrides: vec![],
+            output: None,
+            jobs,
+            jobs_sender,
+            confirmation: None,
+            last_selected: None,
+            open_with: None,
+            preview,
+            views: Views::default(),
+            sessions_dir: PathBuf::new(),
         }
     }
 
@@ -135,21 +192,63 @@ impl<'a> Application<'a> {
         if let Ok(modules) = self.status.git_modules.lock() {
             git_modules = modules.clone();
         }
-        let frame_width = self.terminal.get_frame().size().width as usize;
+        // The tree shares the screen with the preview pane.
+        let tree_width = if self.configuration.preview {
+            tree_and_preview(size)[0].width
+        } else {
+            size.width
+        };
         let files: Vec<ListItem> =
-            self.item_list(0, frame_width, &git_modules, &self.configuration)?;
+            self.item_list(0, tree_width as usize, &git_modules, &self.configuration)?;
         let pathbar = self.pathbar()?;
         let statusbar = self.statusbar(
             size.width as usize,
             self.command_bar.command_entry_mode,
             self.command_bar.input_text.clone(),
         )?;
+        let completion_menu = self.completion_menu(size);
+        let open_with_menu = self.open_with_menu(size);
+        let output_height = match &self.output {
+            Some(_) => output_pane_height(size.height),
+            None => 0,
+        };
+        let output_pane = self.output.as_ref().map(|output| {
+            let lines: Vec<Spans> = output
+                .visible_lines(output_height.saturating_sub(1) as usize)
+                .into_iter()
+                .map(Spans::from)
+                .collect();
+            let title = Span::styled(output.title(), self.configuration.style.status_line.style());
+            Paragraph::new(lines)
+                .style(self.configuration.style.default.style())
+                .block(Block::default().borders(Borders::TOP).title(title))
+        });
+        if self.configuration.preview {
+            let selected = self.selected().map(|file| file.path);
+            self.preview.update(
+                selected.as_deref(),
+                self.configuration.preview_lines,
+                &self.configuration.preview_theme,
+            );
+        }
+        let preview_pane = self.configuration.preview.then(|| {
+            let lines: Vec<Spans> = self
+                .preview
+                .visible_lines(size.height as usize)
+                .into_iter()
+                .map(Spans::from)
+                .collect();
+            Paragraph::new(lines)
+                .style(self.configuration.style.default.style())
+                .block(Block::default().borders(Borders::LEFT))
+        });
+        let style = &self.configuration.style;
 
         let _ = self.terminal.draw(|frame| {
             // Draw each visible file in the tree until we run out of space on the screen.
             let filelist = List::new(files)
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().bg(Color::Rgb(39, 42, 45)))
+                .style(style.default.style())
+                .highlight_style(style.cursor_line.style())
                 .highlight_symbol("");
 
             let chunks = Layout::default()
@@ -157,7 +256,8 @@ impl<'a> Application<'a> {
                 .constraints(
                     [
                         Constraint::Length(1),
-                        Constraint::Length(size.height.saturating_sub(2)),
+                        Constraint::Length(size.height.saturating_sub(2 + output_height)),
+                        Constraint::Length(output_height),
                         Constraint::Length(1),
                     ]
                     .as_ref(),
@@ -165,14 +265,32 @@ impl<'a> Application<'a> {
                 .split(size);
 
             frame.render_widget(pathbar, chunks[0]);
-            frame.render_stateful_widget(filelist, chunks[1], &mut self.list_state);
-            frame.render_widget(statusbar, chunks[2]);
+            match preview_pane {
+                Some(preview_pane) => {
+                    let panes = tree_and_preview(chunks[1]);
+                    frame.render_stateful_widget(filelist, panes[0], &mut self.list_state);
+                    frame.render_widget(preview_pane, panes[1]);
+                }
+                None => frame.render_stateful_widget(filelist, chunks[1], &mut self.list_state),
+            }
+            if let Some(output_pane) = output_pane {
+                frame.render_widget(output_pane, chunks[2]);
+            }
+            frame.render_widget(statusbar, chunks[3]);
+            if let Some((menu, area, mut state)) = completion_menu {
+                frame.render_widget(widgets::Clear, area);
+                frame.render_stateful_widget(menu, area, &mut state);
+            }
+            if let Some((menu, area, mut state)) = open_with_menu {
+                frame.render_widget(widgets::Clear, area);
+                frame.render_stateful_widget(menu, area, &mut state);
+            }
         })?;
         if self.command_bar.command_entry_mode {
             execute!(
                 self.terminal.backend_mut(),
                 cursor::MoveTo(
-                    (self.command_bar.input_text.len() + self.command_bar.prompt_text.len()) as u16,
+                    (self.command_bar.cursor_column() as u16).min(size.width.saturating_sub(1)),
                     size.height
                 ),
                 cursor::Show
@@ -181,21 +299,110 @@ impl<'a> Application<'a> {
         Ok(())
     }
 
+    /// Builds the "Open with" menu in the middle of the screen.
+    fn open_with_menu(&self, size: Rect) -> Option<(List<'a>, Rect, ListState)> {
+        let menu = self.open_with.as_ref()?;
+        let items: Vec<ListItem> = menu
+            .openers
+            .iter()
+            .enumerate()
+            .map(|(index, opener)| ListItem::new(format!(" {} {} ", index + 1, opener.name())))
+            .collect();
+        let title = format!(
+            " Open {} with ",
+            menu.path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default()
+        );
+        let width = items
+            .iter()
+            .map(|item| item.width() as u16)
+            .chain([title.width() as u16])
+            .max()
+            .unwrap_or_default()
+            .saturating_add(2)
+            .min(size.width);
+        let height = (items.len() as u16 + 2).min(size.height.saturating_sub(2));
+        let area = Rect::new(
+            size.width.saturating_sub(width) / 2,
+            size.height.saturating_sub(height) / 2,
+            width,
+            height,
+        );
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .style(self.configuration.style.command_line.style())
+            .highlight_style(Style::default().bg(Color::Rgb(53, 57, 62)));
+        Some((list, area, menu.state.clone()))
+    }
+
+    /// Builds the completion menu shown above the status bar when there are several candidates.
+    fn completion_menu(&self, size: Rect) -> Option<(List<'a>, Rect, ListState)> {
+        let completion = self.command_bar.completion.as_ref()?;
+        if completion.candidates.len() < 2 {
+            return None;
+        }
+        let height = (completion.candidates.len() as u16)
+            .min(COMPLETION_MENU_HEIGHT)
+            .min(size.height.saturating_sub(2));
+        let width = completion
+            .candidates
+            .iter()
+            .map(|candidate| candidate.width() as u16 + 2)
+            .max()
+            .unwrap_or_default()
+            .min(size.width);
+        // Align the menu with the start of the completed word.
+        let column = self.command_bar.prompt().width()
+            + self.command_bar.input_text[..completion.start].width();
+        let area = Rect::new(
+            (column as u16).min(size.width - width),
+            size.height.saturating_sub(height + 1),
+            width,
+            height,
+        );
+        let items: Vec<ListItem> = completion
+            .candidates
+            .iter()
+            .map(|candidate| ListItem::new(format!(" {} ", candidate)))
+            .collect();
+        let menu = List::new(items)
+            .style(self.configuration.style.command_line.style())
+            .highlight_style(Style::default().bg(Color::Rgb(53, 57, 62)));
+        let mut state = ListState::default();
+        state.select(completion.selected);
+        Some((menu, area, state))
+    }
+
+    /// Completes the command bar input, paths are relative to where the command operates.
+    pub fn complete(&mut self) {
+        let input = &self.command_bar.input_text;
+        let base = if input.starts_with("new-file ") || input.starts_with("new-dir ") {
+            self.target_dir()
+        } else {
+            self.files.path.clone()
+        };
+        let keys = self.configuration.keys();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        self.command_bar.complete(&base, &keys);
+    }
+
     pub fn item_list(
         &self,
         indent: usize,
-        frame_width: usize,
+        width: usize,
         git_modules: &HashSet<PathBuf>,
         config: &Config,
     ) -> Result<Vec<ListItem<'a>>, Error> {
         let root = &self.files;
-        self.item_file(indent, frame_width, git_modules, config, root)
+        self.item_file(indent, width, git_modules, config, root)
     }
 
     fn item_file(
         &self,
         indent: usize,
-        frame_width: usize,
+        width: usize,
         git_modules: &HashSet<PathBuf>,
         config: &Config,
         file: &File,
@@ -204,9 +411,10 @@ impl<'a> Application<'a> {
         if !file.descendants.is_empty() {
             for descendant in &file.descendants {
                 let mut mark_span = Span::styled(" ", Style::default());
-                if self.cut.contains(&descendant.path) {
+                let shared_path = self.shared_path(&descendant.path);
+                if self.cut.contains(&shared_path) {
                     mark_span = Span::styled("●", Style::default().fg(Color::Red));
-                } else if self.copied.contains(&descendant.path) {
+                } else if self.copied.contains(&shared_path) {
                     mark_span = Span::styled("●", Style::default().fg(Color::Yellow));
                 } else if self.marked.contains(&descendant.path) {
                     mark_span = Span::styled("●", Style::default().fg(Color::Magenta));
@@ -226,83 +434,46 @@ impl<'a> Application<'a> {
                     indent_span.0.extend(final_span.0);
                 }
                 //let git_modified_span = git_modified(descendant.clone())?;
-                let mut count_span = descendant.info_count()?;
+                let mut count_span = descendant.info_count(self.vfs.as_ref())?;
                 let item_name = descendant
                     .path
                     .file_name()
                     .ok_or(anyhow!("invalid path"))?
                     .to_string_lossy();
                 let icon_width = 3;
-                let item_pad_width = frame_width
-                    - (icon_width
+                let item_pad_width = width.saturating_sub(
+                    icon_width
                         + indent_span.width()
                         + item_name.len()
                         + separator_span.width()
                         + mark_span.width()
                         + separator_span.width()
-                        + count_span.width()); // TODO: What if frame has not enough space.
+                        + count_span.width(),
+                );
                 let item_pad_span = Span::raw(format!("{:<item_pad_width$}", " "));
-                let item_span: Span;
-                if descendant.metadata_extra.is_symlink() {
-                    count_span.style = Style::default().fg(Color::Cyan);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.link.icon, item_name),
-                        Style::default().fg(Color::Cyan),
-                    );
-                } else if descendant.is_video() {
-                    count_span.style = Style::default().fg(Color::Magenta);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.video.icon, item_name),
-                        Style::default().fg(Color::Magenta),
-                    );
-                } else if descendant.is_audio() {
-                    count_span.style = Style::default().fg(Color::Cyan);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.audio.icon, item_name),
-                        Style::default().fg(Color::Cyan),
-                    );
-                } else if descendant.is_image() {
-                    count_span.style = Style::default().fg(Color::Magenta);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.image.icon, item_name),
-                        Style::default().fg(Color::Magenta),
-                    );
-                } else if descendant.is_archive() {
-                    count_span.style = Style::default().fg(Color::Red);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.archive.icon, item_name),
-                        Style::default().fg(Color::Red),
-                    );
-                } else if descendant.is_document() {
-                    count_span.style = Style::default().fg(Color::White);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.document.icon, item_name),
-                        Style::default().fg(Color::White),
-                    );
-                } else if descendant.metadata.is_dir() {
-                    if git_modules.contains(&descendant.path) {
-                        count_span.style = Style::default().fg(Color::Cyan);
-                        item_span = Span::styled(
-                            format!("{}  {}", config.style.directory.icon, item_name),
-                            Style::default().fg(Color::Cyan),
-                        );
-                    } else {
-                        count_span.style = Style::default().fg(Color::Blue);
-                        item_span = Span::styled(
-                            format!("{}  {}", config.style.directory.icon, item_name),
-                            Style::default().fg(Color::Blue),
-                        );
-                    }
+                let file_style = if descendant.stat.is_symlink {
+                    &config.style.link
+                } else if let Some(file_style) = config.file_style(&descendant.path) {
+                    file_style
+                } else if descendant.is_dir() {
+                    &config.style.directory
                 } else if descendant.is_executable() {
-                    count_span.style = Style::default().fg(Color::Green);
-                    item_span = Span::styled(
-                        format!("{}  {}", config.style.file.icon, item_name),
-                        Style::default().fg(Color::Green),
-                    );
+                    &config.style.executable
                 } else {
-                    count_span.style = Style::default();
-                    item_span = Span::raw(format!("{}  {}", config.style.file.icon, item_name));
-                }
+                    &config.style.file
+                };
+                // Git submodules are shown in the link colour.
+                let style = if git_modules.contains(&descendant.path) {
+                    config.style.link.style()
+                } else if descendant.member.is_some() {
+                    file_style
+                        .style()
+                        .patch(config.style.archive_member.style())
+                } else {
+                    file_style.style()
+                };
+                count_span.style = style;
+                let item_span = Span::styled(format!("{}  {}", file_style.icon, item_name), style);
                 let list_item = Spans::from(vec![
                     item_span,
                     separator_span.clone(),
@@ -315,7 +486,7 @@ impl<'a> Application<'a> {
                 items.push(ListItem::new(indent_span));
                 if !descendant.descendants.is_empty() {
                     let mut descendant_items =
-                        self.item_file(indent + 1, frame_width, git_modules, config, descendant)?;
+                        self.item_file(indent + 1, width, git_modules, config, descendant)?;
                     items.append(&mut descendant_items);
                 }
             }
@@ -330,35 +501,70 @@ impl<'a> Application<'a> {
         input: String,
     ) -> Result<Paragraph<'a>, Error> {
         let statusbar: Paragraph;
-        if commandbar {
+        if let (false, Some(message)) = (commandbar, &self.command_bar.message) {
+            let (text, style) = match message {
+                Message::Info(text) => (text.clone(), Style::default()),
+                Message::Error(text) => (text.clone(), Style::default().fg(Color::Red)),
+            };
+            statusbar = Paragraph::new(vec![Spans::from(vec![Span::styled(text, style)])])
+                .block(Block::default().borders(Borders::NONE))
+                .style(self.configuration.style.status_line.style())
+                .alignment(Alignment::Left)
+                .wrap(Wrap { trim: true });
+        } else if commandbar {
             statusbar = Paragraph::new(vec![Spans::from(vec![
-                Span::styled(self.command_bar.prompt_text.clone(), Style::default()),
+                Span::styled(self.command_bar.prompt(), Style::default()),
                 Span::styled(input, Style::default()),
             ])])
             .block(Block::default().borders(Borders::NONE))
-            .style(Style::default().fg(Color::White).bg(Color::Rgb(39, 42, 45)))
+            .style(self.configuration.style.command_line.style())
             .alignment(Alignment::Left)
             .wrap(Wrap { trim: true });
         } else {
-            let mut git_status_span = Spans::from("");
-            let mut module_count_span = Spans::from("");
-            //let mut commit_count_span = Spans::from("");
-            let mut code_lines_span = Spans::from("");
-            if let Ok(output) = self.status.git_status.lock() {
-                git_status_span = Application::status_git_status_span(output.clone());
-            }
-            //if let Ok(output) = self.commit_count.lock() {
-            //    commit_count_span = Application::status_commit_count_span(output.clone());
-            //}
-            if let Ok(output) = self.status.code_lines.lock() {
-                code_lines_span = Application::status_code_lines_span(output.clone());
-            }
-            if git_status_span.width() != 0 {
+            let status = &self.configuration.status;
+            let left = self.status_segments(&status.left);
+            let center = self.status_segments(&status.center);
+            let right = self.status_segments(&status.right);
+            // Center the middle segments and push the right segments to the edge.
+            let left_pad = (width.saturating_sub(center.width()) / 2).saturating_sub(left.width());
+            let right_pad =
+                width.saturating_sub(left.width() + left_pad + center.width() + right.width());
+            let mut status_span = left;
+            status_span.0.push(Span::raw(" ".repeat(left_pad)));
+            status_span.0.extend(center.0);
+            status_span.0.push(Span::raw(" ".repeat(right_pad)));
+            status_span.0.extend(right.0);
+            statusbar = Paragraph::new(status_span)
+                .block(Block::default().borders(Borders::NONE))
+                .style(self.configuration.style.status_line.style())
+                .alignment(Alignment::Left)
+                .wrap(Wrap { trim: false });
+        }
+        Ok(statusbar)
+    }
+
+    /// Joins the status bar segments with the given names.
+    fn status_segments(&self, names: &[String]) -> Spans<'a> {
+        let mut spans = Spans::from(vec![]);
+        for name in names {
+            spans.0.extend(self.status_segment(name).0);
+        }
+        spans
+    }
+
+    fn status_segment(&self, name: &str) -> Spans<'a> {
+        let output = |value: &Arc<Mutex<String>>| {
+            value.lock().map(|value| value.clone()).unwrap_or_default()
+        };
+        let git_status = output(&self.status.git_status);
+        match name {
+            "git_branch" => Application::status_git_status_span(git_status),
+            "git_submodules" if !git_status.is_empty() => {
                 let mut module_count = 0;
                 if let Ok(modules) = self.status.git_modules.lock() {
-                    module_count = modules.iter().count();
+                    module_count = modules.len();
                 }
-                module_count_span = Spans::from(vec![
+                Spans::from(vec![
                     Span::styled(
                         format!("{}  ", self.configuration.style.directory.icon),
                         Style::default().fg(Color::Cyan),
@@ -367,34 +573,17 @@ impl<'a> Application<'a> {
                         format!("{}  ", module_count),
                         Style::default().fg(Color::Cyan),
                     ),
-                ]);
+                ])
             }
-            let link_target_span = self.status_link_target();
-            let pad_width = width
-                - (git_status_span.width()
-                    + module_count_span.width()
-                    //+ commit_count_span.width()
-                    + code_lines_span.width()
-                    + link_target_span.width());
-            let mut status_span = Spans::from(vec![]);
-            status_span.0.extend(git_status_span.0);
-            status_span.0.extend(module_count_span.0);
-            //status_span.0.extend(commit_count_span.0);
-            status_span.0.extend(code_lines_span.0);
-            status_span.0.extend(vec![
-                link_target_span,
-                Span::styled(
-                    format!("{:>pad_width$}", self.status_position().content),
-                    Style::default(),
-                ),
-            ]);
-            statusbar = Paragraph::new(status_span)
-                .block(Block::default().borders(Borders::NONE))
-                .style(Style::default().fg(Color::White).bg(Color::Rgb(39, 42, 45)))
-                .alignment(Alignment::Left)
-                .wrap(Wrap { trim: false });
+            "lines_of_code" => Application::status_code_lines_span(output(&self.status.code_lines)),
+            "commit_count" => {
+                Application::status_commit_count_span(output(&self.status.commit_count))
+            }
+            "link" => Spans::from(self.status_link_target()),
+            "item_count" => Spans::from(self.status_position()),
+            // Files are not watched so there is nothing to show for `file_watcher_count`.
+            _ => Spans::from(""),
         }
-        Ok(statusbar)
     }
 
     pub fn status_position(&self) -> Span<'a> {
@@ -414,12 +603,18 @@ impl<'a> Application<'a> {
     pub fn status_link_target(&self) -> Span<'a> {
         let icon = "  ";
         if let Some(selected) = self.selected() {
-            if selected.metadata_extra.is_symlink() {
-                let target = fs::read_link(selected.path).expect("could not read link");
-                return Span::styled(
-                    format!("{}{}  ", icon, target.to_string_lossy()),
-                    Style::default(),
-                );
+            if selected.stat.is_symlink {
+                // Reading a remote link can fail, the error is shown in place of the target.
+                return match self.vfs.read_link(&selected.path) {
+                    Ok(target) => Span::styled(
+                        format!("{}{}  ", icon, target.to_string_lossy()),
+                        Style::default(),
+                    ),
+                    Err(error) => Span::styled(
+                        format!("{}could not read link: {}  ", icon, error),
+                        Style::default().fg(Color::Red),
+                    ),
+                };
             }
         }
         Span::styled("", Style::default())
@@ -549,7 +744,7 @@ impl<'a> Application<'a> {
             //Span::styled("  sample    ghi    qux", Style::default()),
         ])])
         .block(Block::default().borders(Borders::NONE))
-        .style(Style::default().fg(Color::White).bg(Color::Rgb(39, 42, 45)))
+        .style(self.configuration.style.path_line.style())
         .alignment(Alignment::Left)
         .wrap(Wrap { trim: true });
         Ok(pathbar)
@@ -585,7 +780,7 @@ impl<'a> Application<'a> {
     pub fn collapse(&mut self) {
         let mut collapsed: Option<PathBuf> = None;
         if let Some(selected) = self.selected_mut() {
-            if selected.metadata.is_dir() {
+            if selected.is_dir() || selected.is_archive() {
                 selected.descendants = vec![];
                 collapsed = Some(selected.path.clone());
             }
@@ -598,10 +793,36 @@ impl<'a> Application<'a> {
     pub fn expand(&mut self) {
         let mut expanded: Option<PathBuf> = None;
         let show_hidden = self.configuration.show_hidden;
+        let sort = self.configuration.sort.clone();
+        let vfs = Arc::clone(&self.vfs);
+        // Archives and directories inside them are listed from the archive.
+        let mut members = None;
+        if let Some(selected) = self.selected() {
+            if selected.is_archive() || selected.member.as_ref().is_some_and(|m| m.is_dir) {
+                match self.read_archive(&selected) {
+                    Ok(Some(descendants)) => members = Some(descendants),
+                    // The archive is shown expanded once its members are listed.
+                    Ok(None) => {
+                        let message = format!("listing {}", selected.path.display());
+                        self.command_bar.message = Some(Message::Info(message));
+                        members = Some(vec![]);
+                    }
+                    Err(error) => {
+                        let message = format!("{}: {}", selected.path.display(), error);
+                        self.command_bar.message = Some(Message::Error(message));
+                        return;
+                    }
+                }
+            }
+        }
         if let Some(selected) = self.selected_mut() {
-            if selected.metadata.is_dir() {
-                let root = Application::read_dir(selected.path.clone(), show_hidden)
-                    .expect("could not read directory");
+            if let Some(descendants) = members {
+                selected.descendants = descendants;
+                expanded = Some(selected.path.clone());
+            } else if selected.member.is_none() && selected.is_dir() {
+                let root =
+                    Application::read_dir(vfs.as_ref(), selected.path.clone(), show_hidden, &sort)
+                        .expect("could not read directory");
                 *selected = root;
                 expanded = Some(selected.path.clone());
             }
@@ -623,6 +844,7 @@ impl<'a> Application<'a> {
 
     pub fn refresh(&mut self) {
         self.synchronize().expect("synchronization failed");
+        self.preview.invalidate();
         self.updater.send(()).expect("could not send to a channel");
         if let Some(selected) = self.list_state.selected() {
             // Re-read the whole tree at current root.
@@ -648,43 +870,199 @@ impl<'a> Application<'a> {
         }
     }
 
+    /// Loads the configuration layers again and applies them. The current configuration
+    /// is kept if any of the files is invalid.
+    pub fn reload_config(&mut self) -> Result<(), Error> {
+        let configuration = config::load_config(&self.config_path, &self.config_root);
+        // Key bindings may refer to actions defined in another layer.
+        let actions: Vec<String> = configuration
+            .as_ref()
+            .map(|configuration| configuration.actions.keys().cloned().collect())
+            .unwrap_or_default();
+        for path in config::config_layers(&self.config_path, &self.config_root) {
+            if let Some(diagnostic) = check::check_config(&path, &actions)?.first() {
+                return Err(anyhow!("{}:{}", path.display(), diagnostic));
+            }
+        }
+        let mut configuration = configuration?;
+        for expression in &self.config_overrides {
+            configuration.set(expression)?;
+        }
+        self.keymap = Keymap::new(&configuration.keys)?;
+        // The view of the root is kept unless it is the configured one.
+        self.save_view();
+        self.views.default = View::new(&configuration);
+        self.configuration = configuration;
+        self.restore_view(&self.files.path.clone());
+        self.refresh();
+        Ok(())
+    }
+
+    /// Trusts the current contents of the project configuration, which lets it set keys
+    /// running commands like hooks and actions.
+    pub fn trust(&mut self) -> Result<(), Error> {
+        let path = config::project_config(&self.config_root).ok_or_else(|| {
+            anyhow!(
+                "no {} in {}",
+                config::PROJECT_CONFIG,
+                self.config_root.display()
+            )
+        })?;
+        config::trust(&path)?;
+        self.reload_config()?;
+        self.command_bar.message = Some(Message::Info(format!("trusted {}", path.display())));
+        Ok(())
+    }
+
     pub fn selected_mut(&mut self) -> Option<&mut File> {
         let selected = self.list_state.selected()?;
         find_target_file(&mut self.files, &mut 0, selected + 1)
     }
 
     pub fn jump_root(&mut self, path: PathBuf) -> Result<(), Error> {
-        if path.is_dir() {
-            env::set_current_dir(path.clone());
+        if self.vfs.stat(&path).is_ok_and(|stat| stat.is_dir) {
+            let previous_root = self.files.path.clone();
+            self.set_current_dir(&path);
             self.updater.send(())?;
-            let root = Application::read_dir(path.clone(), self.configuration.show_hidden)?;
-            self.files = root;
-            if self.files.is_empty() {
+            self.save_view();
+            self.restore_view(&path);
+            self.files = self.read_tree(path)?;
+            if self.files.is_empty(self.vfs.as_ref()) {
                 self.list_state.select(None);
             } else {
                 self.list_state.select(Some(0));
             }
-            self.expanded = HashSet::new();
             self.set_title()?;
+            self.hook_cd(&previous_root);
         }
         Ok(())
     }
 
+    /// Remembers how the current root is shown, keyed by its shared path.
+    pub fn save_view(&mut self) {
+        let root = self.files.path.clone();
+        let mut expanded: Vec<PathBuf> = self
+            .expanded
+            .iter()
+            .filter_map(|path| path.strip_prefix(&root).ok())
+            .map(Path::to_owned)
+            .collect();
+        expanded.sort();
+        let view = View {
+            sort: self.configuration.sort.clone(),
+            show_hidden: self.configuration.show_hidden,
+            expanded,
+        };
+        if let Err(error) = self.views.save(&self.shared_path(&root), view) {
+            error!("could not save the view of {}: {}", root.display(), error);
+        }
+    }
+
+    /// Shows a new root the way it was last shown, the tree has to be read again afterwards.
+    pub fn restore_view(&mut self, root: &Path) {
+        let view = self.views.get(&self.shared_path(root));
+        self.configuration.sort = view.sort;
+        self.configuration.show_hidden = view.show_hidden;
+        self.expanded = view.expanded.iter().map(|path| root.join(path)).collect();
+    }
+
+    /// Saves the root, expanded directories, selection, marks and scroll position.
+    pub fn save_session(&self, name: &str) -> Result<(), Error> {
+        let root = &self.files.path;
+        let relative = |paths: &HashSet<PathBuf>| {
+            let mut paths: Vec<PathBuf> = paths
+                .iter()
+                .filter_map(|path| path.strip_prefix(root).ok())
+                .map(Path::to_owned)
+                .collect();
+            paths.sort();
+            paths
+        };
+        let selected = self.selected().map(|file| file.path);
+        let session = Session {
+            root: self.shared_path(root),
+            expanded: relative(&self.expanded),
+            selected: selected.and_then(|path| path.strip_prefix(root).ok().map(Path::to_owned)),
+            marked: relative(&self.marked),
+            offset: self.list_state.offset(),
+        };
+        session.save(&self.sessions_dir, name)
+    }
+
+    /// Changes to the root of a saved session and restores it.
+    pub fn load_session(&mut self, name: &str) -> Result<(), Error> {
+        let session = Session::load(&self.sessions_dir, name)?;
+        let (vfs, root) = vfs::resolve(&session.root)?;
+        // The filesystem of a running instance doesn't change.
+        if vfs.url(&root) != self.vfs.url(&root) {
+            return Err(anyhow!(
+                "session {} is on another filesystem, open it with fm --restore {}",
+                name,
+                name
+            ));
+        }
+        if root != self.files.path {
+            self.jump_root(root)?;
+        }
+        self.restore_session(&session)
+    }
+
+    /// Expands, marks and selects the files of a session saved in the current root.
+    pub fn restore_session(&mut self, session: &Session) -> Result<(), Error> {
+        let root = self.files.path.clone();
+        self.expanded = session
+            .expanded
+            .iter()
+            .map(|path| root.join(path))
+            .collect();
+        self.marked = session.marked.iter().map(|path| root.join(path)).collect();
+        self.refresh();
+        self.scroll_to(session.offset)?;
+        if let Some(selected) = &session.selected {
+            self.select_path(&root.join(selected));
+        }
+        Ok(())
+    }
+
+    /// Scrolls the tree so the file at `offset` is the first one shown. The list only
+    /// scrolls as far as needed to show the selected file, so the last file which would be
+    /// visible is selected while drawing once.
+    fn scroll_to(&mut self, offset: usize) -> Result<(), Error> {
+        let selected = self.list_state.selected();
+        let height = self.terminal.size()?.height.saturating_sub(2) as usize;
+        if offset > 0 && height > 0 {
+            self.list_state.select(Some(offset + height - 1));
+            self.draw()?;
+        }
+        self.list_state.select(selected);
+        Ok(())
+    }
+
+    /// Makes a new root the working directory of commands, which stays where fm was started
+    /// while a remote filesystem is browsed.
+    fn set_current_dir(&self, path: &Path) -> io::Result<()> {
+        if self.vfs.url(path).is_some() {
+            return Ok(());
+        }
+        env::set_current_dir(path)
+    }
+
     pub fn change_root(&mut self) -> Result<(), Error> {
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                env::set_current_dir(selected.path.clone());
+            if selected.member.is_none() && selected.is_dir() {
+                let previous_root = self.files.path.clone();
+                self.set_current_dir(&selected.path);
                 self.updater.send(())?;
-                let root =
-                    Application::read_dir(selected.path.clone(), self.configuration.show_hidden)?;
-                self.files = root;
-                if selected.is_empty() {
+                self.save_view();
+                self.restore_view(&selected.path);
+                self.files = self.read_tree(selected.path.clone())?;
+                if selected.is_empty(self.vfs.as_ref()) {
                     self.list_state.select(None);
                 } else {
                     self.list_state.select(Some(0));
                 }
-                self.expanded = HashSet::new();
                 self.set_title()?;
+                self.hook_cd(&previous_root);
             }
         }
         Ok(())
@@ -695,14 +1073,16 @@ impl<'a> Application<'a> {
         // If there is no previous root saved, go up one level.
         let root = self.files.path.clone();
         if let Some(path) = root.parent() {
-            env::set_current_dir(path)?;
+            self.set_current_dir(path)?;
             self.updater.send(())?;
-            let root = Application::read_dir(path.to_owned(), self.configuration.show_hidden)?;
+            self.save_view();
+            self.restore_view(path);
+            let root = self.read_tree(path.to_owned())?;
             self.files_previous = self.files.path.clone();
             self.files = root;
             self.list_state.select(Some(0));
-            self.expanded = HashSet::new();
             self.set_title()?;
+            self.hook_cd(&self.files_previous);
         }
         // Position the current line on the child from which we moved.
         if let Some(name) = self.files_previous.file_name() {
@@ -728,63 +1108,124 @@ impl<'a> Application<'a> {
         }
     }
 
-    pub fn new_dir(&mut self, name: String) {
+    /// Returns the directory new files are created in: the selected directory,
+    /// the parent of the selected file or the root if nothing is selected.
+    pub fn target_dir(&self) -> PathBuf {
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("fm-new-dir")
-                    .arg(selected.path)
-                    .arg(name)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-                self.refresh();
+            if selected.is_dir() {
+                return selected.path;
             } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("fm-new-dir")
-                    .arg(parent)
-                    .arg(name)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-                self.refresh();
+                return parent.to_owned();
             }
-        } else {
-            let mut child = process::Command::new("fm-new-dir")
-                .arg(self.files.path.clone())
-                .arg(name)
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
-            self.refresh();
         }
+        self.files.path.clone()
+    }
+
+    pub fn new_dir(&mut self, names: Vec<String>) -> Result<(), Error> {
+        let parent = self.target_dir();
+        let mut created = vec![];
+        let result = names
+            .iter()
+            .flat_map(|name| files::expand_braces(name))
+            .try_for_each(|name| -> Result<(), Error> {
+                let path = Application::new_path(&parent, &name)?;
+                vfs::create_dir_all(self.vfs.as_ref(), &path)?;
+                created.push(path);
+                Ok(())
+            });
+        self.reveal(&created);
+        result
+    }
+
+    pub fn new_file(&mut self, names: Vec<String>) -> Result<(), Error> {
+        let parent = self.target_dir();
+        let templates = config::templates_dir();
+        let mut created = vec![];
+        let result = names
+            .iter()
+            .flat_map(|name| files::expand_braces(name))
+            .try_for_each(|name| -> Result<(), Error> {
+                let path = Application::new_path(&parent, &name)?;
+                // A trailing slash creates a directory instead of a file.
+                if name.ends_with('/') {
+                    vfs::create_dir_all(self.vfs.as_ref(), &path)?;
+                } else if self.vfs.url(&path).is_some() {
+                    self.create_remote_file(&path, &templates)?;
+                } else {
+                    files::create_file(&path, &templates)?;
+                }
+                created.push(path);
+                Ok(())
+            });
+        self.reveal(&created);
+        result
+    }
+
+    /// Joins the name of a new file or directory to the directory it is created in.
+    /// Absolute names and `..` components are rejected so nothing is created outside of it.
+    pub fn new_path(parent: &Path, name: &str) -> Result<PathBuf, Error> {
+        archive::normalize(Path::new(name))
+            .map(|name| parent.join(name))
+            .ok_or_else(|| anyhow!("invalid name: {}", name))
+    }
+
+    /// Creates a new file like [`files::create_file`] on a remote filesystem.
+    fn create_remote_file(&self, path: &Path, templates: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            vfs::create_dir_all(self.vfs.as_ref(), parent)?;
+        }
+        if self.vfs.stat(path).is_ok() {
+            return Err(anyhow!("{} already exists", path.display()));
+        }
+        let mut file = self.vfs.create(path)?;
+        if let Some(extension) = path.extension() {
+            let template = templates.join(extension);
+            if template.is_file() {
+                file.write_all(&fs::read(template)?)?;
+            }
+        }
+        Ok(())
     }
 
-    pub fn new_file(&mut self, name: String) {
-        if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("fm-new-file")
-                    .arg(selected.path)
-                    .arg(name)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-                self.refresh();
-            } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("fm-new-file")
-                    .arg(parent)
-                    .arg(name)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-                self.refresh();
+    /// Fails on a remote filesystem, where `fs` calls and external programs would act on
+    /// the local file with the same path instead.
+    fn check_local(&self, operation: &str) -> Result<(), Error> {
+        if self.vfs.url(&self.files.path).is_some() {
+            return Err(anyhow!(
+                "{} is not supported on a remote filesystem",
+                operation
+            ));
+        }
+        Ok(())
+    }
+
+    /// Fails for files inside an archive, which external programs can't find.
+    fn check_members(&self, operation: &str, paths: &[PathBuf]) -> Result<(), Error> {
+        if paths.iter().any(|path| archive::split(path).is_some()) {
+            return Err(anyhow!("{} is not supported inside an archive", operation));
+        }
+        Ok(())
+    }
+
+    /// Expands all directories leading to the given paths and selects the first one.
+    pub fn reveal(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            for ancestor in path.ancestors().skip(1) {
+                if ancestor == self.files.path || !ancestor.starts_with(&self.files.path) {
+                    break;
+                }
+                self.expanded.insert(ancestor.to_owned());
             }
-        } else {
-            let mut child = process::Command::new("fm-new-file")
-                .arg(self.files.path.clone())
-                .arg(name)
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
-            self.refresh();
+        }
+        self.refresh();
+        if let Some(path) = paths.first() {
+            self.select_path(path);
+        }
+    }
+
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.files.iter().position(|file| file.path == path) {
+            self.list_state.select(Some(index.saturating_sub(1)));
         }
     }
 
@@ -792,27 +1233,44 @@ impl<'a> Application<'a> {
         let marked = self.marked();
         if marked.is_empty() {
             if let Some(selected) = self.selected() {
-                self.copied.insert(selected.path.clone());
+                self.copied.insert(self.shared_path(&selected.path));
             }
         } else {
-            self.copied.extend(self.marked.iter().cloned());
+            let marked: Vec<PathBuf> = marked.iter().map(|path| self.shared_path(path)).collect();
+            self.copied.extend(marked);
             self.marked.clear();
         }
         self.send_copied().expect("could not send copied");
     }
 
-    pub fn cut(&mut self) {
+    /// Files inside an archive can't be cut since they can't be removed from it.
+    pub fn cut(&mut self) -> Result<(), Error> {
         let marked = self.marked();
+        let mut paths = marked.clone();
+        if paths.is_empty() {
+            paths.extend(self.selected().map(|file| file.path));
+        }
+        self.check_members("cut", &paths)?;
         if marked.is_empty() {
             if let Some(selected) = self.selected() {
-                self.cut.insert(selected.path.clone());
+                self.cut.insert(self.shared_path(&selected.path));
             }
         } else {
-            self.cut.extend(self.marked.iter().cloned());
+            let marked: Vec<PathBuf> = marked.iter().map(|path| self.shared_path(path)).collect();
+            self.cut.extend(marked);
             self.marked.clear();
         }
         // TODO: Handle the error.
         self.send_cut().expect("could not send cut");
+        Ok(())
+    }
+
+    /// Returns the path a file is copied or cut as, so that instances browsing other
+    /// filesystems can paste it e.g. `sftp://me@host/srv/a.txt` for a remote file.
+    pub fn shared_path(&self, path: &Path) -> PathBuf {
+        self.vfs
+            .url(path)
+            .map_or_else(|| path.to_path_buf(), PathBuf::from)
     }
 
     pub fn synchronize(&mut self) -> Result<(), Error> {
@@ -929,19 +1387,248 @@ impl<'a> Application<'a> {
         Ok(())
     }
 
-    pub fn cmd_shell(&mut self, command: String) {
-        let mut child = process::Command::new("zsh")
-            .arg("-c")
-            .arg(command)
-            .spawn()
-            .expect("failed to execute child process");
-        child.wait().expect("shell command failed");
-        self.refresh();
+    /// Creates a command with the selection exported in `fm_*` environment variables.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> process::Command {
+        let mut command = process::Command::new(program);
+        command.envs(self.environment());
+        command
+    }
+
+    /// Environment variables describing the selection, lists of paths are newline separated.
+    pub fn environment(&self) -> Vec<(&'static str, String)> {
+        let selected = self
+            .selected()
+            .map(|file| file.path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        vec![
+            ("fm_file", selected),
+            ("fm_files", join_paths(&self.marked)),
+            ("fm_root", self.files.path.to_string_lossy().into_owned()),
+            ("fm_copied", join_paths(&self.copied)),
+            ("fm_cut", join_paths(&self.cut)),
+        ]
+    }
+
+    /// Creates a command running the input with the configured shell e.g. `zsh -c <input>`.
+    pub fn shell_command(&self, input: &str) -> process::Command {
+        let shell: Vec<&str> = if self.configuration.shell.is_empty() {
+            vec!["sh", "-c"]
+        } else {
+            self.configuration
+                .shell
+                .iter()
+                .map(String::as_str)
+                .collect()
+        };
+        let mut command = self.command(shell[0]);
+        command.args(&shell[1..]).arg(input);
+        command
+    }
+
+    /// Runs the command of a hook in the background if one is configured.
+    /// The event is passed in `$fm_event` and its details in other variables
+    /// besides the usual ones describing the selection.
+    pub fn hook(&self, name: &str, details: &[(&str, String)]) {
+        let Some(input) = self.configuration.hooks.get(name) else {
+            return;
+        };
+        let mut command = self.shell_command(input);
+        command
+            .env("fm_event", name.trim_start_matches("on_"))
+            .envs(details.iter().cloned())
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null());
+        match command.spawn() {
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            }
+            Err(error) => error!("could not run the {} hook: {}", name, error),
+        }
+    }
+
+    fn hook_cd(&self, previous_root: &Path) {
+        let previous_root = previous_root.to_string_lossy().into_owned();
+        self.hook("on_cd", &[("fm_previous_root", previous_root)]);
+    }
+
+    /// Runs the `on_select` hook when another file was selected since the last call.
+    /// The selection made on startup is only recorded.
+    pub fn hook_select(&mut self) {
+        // The index is compared since getting the selected file clones the tree.
+        let selected = (self.files.path.clone(), self.list_state.selected());
+        let Some(last_selected) = self.last_selected.replace(selected.clone()) else {
+            return;
+        };
+        if selected != last_selected
+            && selected.1.is_some()
+            && self.configuration.hooks.get("on_select").is_some()
+        {
+            self.hook("on_select", &[]);
+        }
+    }
+
+    /// Runs a shell command in one of the modes of the `sh` commands.
+    pub fn run_shell(
+        &mut self,
+        command: String,
+        mode: ShellMode,
+        refresh: bool,
+    ) -> Result<(), Error> {
+        match mode {
+            ShellMode::Interactive => self.cmd_shell(command, refresh),
+            ShellMode::Background => self.cmd_shell_background(command, refresh),
+            ShellMode::Capture => self.cmd_shell_capture(command, refresh),
+        }
+    }
+
+    /// Runs a shell command in the terminal with the interface suspended.
+    fn cmd_shell(&mut self, command: String, refresh: bool) -> Result<(), Error> {
+        self.cmd_pre();
+        self.terminal.backend_mut().execute(LeaveAlternateScreen)?;
+        let status = self.shell_command(&command).status();
+        self.cmd_post();
+        if refresh {
+            self.refresh();
+        }
+        match output::exit_message(&command, status?) {
+            Message::Error(error) => Err(anyhow!(error)),
+            Message::Info(_) => Ok(()),
+        }
+    }
+
+    /// Runs a shell command without any output and reports its exit status when it finishes.
+    fn cmd_shell_background(&mut self, command: String, refresh: bool) -> Result<(), Error> {
+        let mut child = self
+            .shell_command(&command)
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .spawn()?;
+        let sender = self.jobs_sender.clone();
+        thread::spawn(move || {
+            let message = match child.wait() {
+                Ok(status) => output::exit_message(&command, status),
+                Err(error) => Message::Error(format!("{}: {}", command, error)),
+            };
+            let _ = sender.send(Report { message, refresh });
+        });
+        Ok(())
+    }
+
+    /// Runs a shell command with its output shown in a pane below the file tree.
+    fn cmd_shell_capture(&mut self, command: String, refresh: bool) -> Result<(), Error> {
+        let output = Output::spawn(
+            self.shell_command(&command),
+            &command,
+            refresh,
+            self.jobs_sender.clone(),
+        )?;
+        self.output = Some(output);
+        Ok(())
+    }
+
+    /// Runs an action from the `[actions]` table, one with `confirm` set waits for `y` first.
+    pub fn action(&mut self, name: &str) -> Result<(), Error> {
+        let action = self
+            .configuration
+            .actions
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown action: {}", name))?;
+        if action.command.trim().is_empty() {
+            return Err(anyhow!("action {} has no command", name));
+        }
+        let command = self.expand_command(&action.command);
+        if action.confirm {
+            self.command_bar.message = Some(Message::Info(format!("{}? [y/N]", command)));
+            self.confirmation = Some((command, action));
+            return Ok(());
+        }
+        self.run_shell(command, action.mode, action.refresh)
+    }
+
+    /// Runs the action waiting for confirmation if it was accepted.
+    pub fn confirm(&mut self, accepted: bool) -> Result<(), Error> {
+        match self.confirmation.take() {
+            Some((command, action)) if accepted => {
+                self.run_shell(command, action.mode, action.refresh)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces the placeholders of an action command with shell quoted paths.
+    /// `%F` is the selected file when no files are marked.
+    pub fn expand_command(&self, template: &str) -> String {
+        let selected = self.selected().map(|file| file.path);
+        let mut marked: Vec<&PathBuf> = self.marked.iter().collect();
+        marked.sort();
+        let quote = |path: &Path| commands::shell_quote(&path.to_string_lossy());
+        commands::expand_placeholders(template, |placeholder| match placeholder {
+            'f' => Some(selected.as_deref().map(quote).unwrap_or_default()),
+            'F' if marked.is_empty() => Some(selected.as_deref().map(quote).unwrap_or_default()),
+            'F' => Some(
+                marked
+                    .iter()
+                    .map(|path| quote(path))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            'd' => Some(quote(&self.target_dir())),
+            'r' => Some(quote(&self.files.path)),
+            _ => None,
+        })
+    }
+
+    /// Scrolls the output pane up by a page.
+    pub fn output_up(&mut self) {
+        let height = self.output_lines();
+        if let Some(output) = &mut self.output {
+            output.scroll_up(height);
+        }
+    }
+
+    /// Scrolls the output pane down by a page.
+    pub fn output_down(&mut self) {
+        let height = self.output_lines();
+        if let Some(output) = &mut self.output {
+            output.scroll_down(height);
+        }
+    }
+
+    /// Scrolls the preview pane up by a page.
+    pub fn preview_up(&mut self) {
+        let height = self.preview_lines();
+        self.preview.scroll_up(height);
+    }
+
+    /// Scrolls the preview pane down by a page.
+    pub fn preview_down(&mut self) {
+        let height = self.preview_lines();
+        self.preview.scroll_down(height);
+    }
+
+    /// Returns the number of lines visible in the preview pane beside the tree.
+    fn preview_lines(&self) -> usize {
+        let height = self.terminal.size().map_or(0, |size| size.height);
+        let output_height = match &self.output {
+            Some(_) => output_pane_height(height),
+            None => 0,
+        };
+        height.saturating_sub(2 + output_height) as usize
+    }
+
+    /// Returns the number of output lines visible below the title of the output pane.
+    fn output_lines(&self) -> usize {
+        let height = self.terminal.size().map_or(0, |size| size.height);
+        output_pane_height(height).saturating_sub(1) as usize
     }
 
     pub fn cmd_path(&self) {
         if let Some(selected) = self.selected() {
-            let mut child = process::Command::new("fm-cmd-path")
+            let mut child = self
+                .command("fm-cmd-path")
                 .arg(selected.path)
                 .spawn()
                 .expect("failed to execute process");
@@ -950,157 +1637,205 @@ impl<'a> Application<'a> {
     }
 
     pub fn cmd_mv(&mut self) {
-        if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("fm-cmd-mv")
-                    .arg(selected.path)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-            } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("fm-cmd-mv")
-                    .arg(parent)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-            }
-        } else {
-            let mut child = process::Command::new("fm-cmd-mv")
-                .arg(self.files.path.clone())
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
-        }
+        let mut child = self
+            .command("fm-cmd-mv")
+            .arg(self.target_dir())
+            .spawn()
+            .expect("failed to execute process");
+        child.wait().expect("child process failed");
         self.refresh();
     }
 
     pub fn cmd_cp(&mut self) {
-        if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("fm-cmd-cp")
-                    .arg(selected.path)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-            } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("fm-cmd-cp")
-                    .arg(parent)
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-            }
+        let mut child = self
+            .command("fm-cmd-cp")
+            .arg(self.target_dir())
+            .spawn()
+            .expect("failed to execute process");
+        child.wait().expect("child process failed");
+        self.refresh();
+    }
+
+    pub fn paste(&mut self, root: bool) -> Result<(), Error> {
+        self.synchronize().expect("synchronization failed");
+        let pasted = !self.copied.is_empty() || !self.cut.is_empty();
+        let target = if root {
+            self.files.path.clone()
         } else {
-            let mut child = process::Command::new("fm-cmd-cp")
-                .arg(self.files.path.clone())
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
+            self.target_dir()
+        };
+        // Files can't be pasted into an archive.
+        if pasted && !self.vfs.stat(&target).is_ok_and(|stat| stat.is_dir) {
+            return Err(anyhow!("cannot paste into {}", target.display()));
+        }
+        let mut failed = HashSet::new();
+        let mut result = Ok(());
+        for (mode, paths) in [("copy", &self.copied), ("cut", &self.cut)] {
+            for path in paths {
+                let outcome = self.paste_file(mode, path, &target);
+                if outcome.is_err() {
+                    failed.insert(path.clone());
+                }
+                result = result.and(outcome);
+            }
         }
+        // Files which couldn't be pasted stay copied or cut so the paste can be retried.
+        let (copied_failed, copied): (HashSet<_>, HashSet<_>) =
+            self.copied.drain().partition(|path| failed.contains(path));
+        let (cut_failed, cut): (HashSet<_>, HashSet<_>) =
+            self.cut.drain().partition(|path| failed.contains(path));
+        self.copied = copied_failed;
+        self.cut = cut_failed;
+        self.marked.clear();
+        self.send_copied()?;
+        self.send_cut()?;
         self.refresh();
+        if !copied.is_empty() || !cut.is_empty() {
+            let details = [
+                ("fm_target", target.to_string_lossy().into_owned()),
+                ("fm_copied", join_paths(&copied)),
+                ("fm_cut", join_paths(&cut)),
+            ];
+            self.hook("on_paste", &details);
+        }
+        result
+    }
+
+    /// Pastes a copied or cut file into a directory with `fm-paste`. Files inside an archive
+    /// are extracted. Files are copied between filesystems when either of them is remote.
+    fn paste_file(&self, mode: &str, path: &Path, target: &Path) -> Result<(), Error> {
+        if let Some((archive, name)) = archive::split(path) {
+            archive::extract(&archive, &name, target)?;
+            return Ok(());
+        }
+        let (source_vfs, source) = vfs::resolve(path)?;
+        if source_vfs.url(&source).is_some() || self.vfs.url(target).is_some() {
+            return self.paste_remote(mode, source_vfs.as_ref(), &source, target);
+        }
+        let mut child = self
+            .command("fm-paste")
+            .arg(mode)
+            .arg(path)
+            .arg(target)
+            .spawn()?;
+        child.wait()?;
+        Ok(())
     }
 
-    pub fn paste(&mut self, root: bool) {
-        // When something is selected in the file list.
-        if let Some(selected) = self.selected() {
-            // Pate to root directory if this option was chosen.
-            if root {
-                self.synchronize().expect("synchronization failed");
-                for path in self.copied.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("copy")
-                        .arg(path.clone())
-                        .arg(self.files.path.clone())
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
-                }
-                for path in self.cut.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("cut")
-                        .arg(path.clone())
-                        .arg(self.files.path.clone())
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
-                }
-                self.clear_files();
-                self.refresh();
-            // When directory is selected.
-            } else if selected.metadata.is_dir() {
-                self.synchronize().expect("synchronization failed");
-                for path in self.copied.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("copy")
-                        .arg(path.clone())
-                        .arg(selected.path.clone())
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
-                }
-                for path in self.cut.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("cut")
-                        .arg(path.clone())
-                        .arg(selected.path.clone())
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
-                }
-                self.clear_files();
-                self.refresh();
-            // When file is selected.
-            } else if let Some(parent) = selected.path.parent() {
-                self.synchronize().expect("synchronization failed");
-                for path in self.copied.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("copy")
-                        .arg(path.clone())
-                        .arg(parent)
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
+    /// Compresses the marked files, or the selected file when none are marked, into an archive
+    /// next to them in the background. The format is taken from the suffix of the name.
+    pub fn compress(&mut self, name: &str) -> Result<(), Error> {
+        self.check_local("compress")?;
+        let mut paths = self.marked();
+        if paths.is_empty() {
+            paths.extend(self.selected().map(|file| file.path));
+        }
+        let Some(dir) = paths.first().and_then(|path| path.parent()) else {
+            return Ok(());
+        };
+        if paths.iter().any(|path| archive::split(path).is_some()) {
+            return Err(anyhow!("cannot compress files inside an archive"));
+        }
+        let archive = dir.join(name);
+        if archive::kind(&archive).is_none() {
+            return Err(anyhow!(
+                "unknown archive format: {} (use .tar.gz, .tar.zst or .zip)",
+                name
+            ));
+        }
+        if archive.exists() {
+            return Err(anyhow!("{} already exists", archive.display()));
+        }
+        self.marked.clear();
+        let sender = self.jobs_sender.clone();
+        let title = format!("compress {}", name);
+        thread::spawn(move || {
+            let mut progress = Progress::new(&title, &sender);
+            let message = match archive::create(&archive, &paths, |count, total| {
+                progress.report(&format!("{}/{} files", count, total))
+            }) {
+                Ok(()) => Message::Info(format!("{}: done", title)),
+                Err(error) => Message::Error(format!("{}: {}", title, error)),
+            };
+            let _ = sender.send(Report {
+                message,
+                refresh: true,
+            });
+        });
+        Ok(())
+    }
+
+    /// Extracts the selected archive into a directory next to it named after the archive, in the
+    /// background. Members which would be written outside that directory are skipped.
+    pub fn extract(&mut self) -> Result<(), Error> {
+        self.check_local("extract")?;
+        let Some(selected) = self.selected() else {
+            return Ok(());
+        };
+        if !selected.is_archive() {
+            return Err(anyhow!("not an archive: {}", selected.path.display()));
+        }
+        let archive = selected.path;
+        let Some(target) = archive::extract_dir(&archive) else {
+            return Err(anyhow!("not an archive: {}", archive.display()));
+        };
+        if target.exists() {
+            return Err(anyhow!("{} already exists", target.display()));
+        }
+        let sender = self.jobs_sender.clone();
+        let title = format!(
+            "extract {}",
+            archive.file_name().unwrap_or_default().to_string_lossy()
+        );
+        thread::spawn(move || {
+            let mut progress = Progress::new(&title, &sender);
+            let message = match archive::extract_all(&archive, &target, |count| {
+                progress.report(&format!("{} files", count))
+            }) {
+                Ok(skipped) if skipped == Skipped::default() => {
+                    Message::Info(format!("{}: done", title))
                 }
-                for path in self.cut.iter() {
-                    let mut child = process::Command::new("fm-paste")
-                        .arg("cut")
-                        .arg(path.clone())
-                        .arg(parent)
-                        .spawn()
-                        .expect("failed to execute process");
-                    child.wait().expect("child process failed");
+                Ok(skipped) => Message::Error(format!("{}: skipped {}", title, skipped)),
+                Err(error) => Message::Error(format!("{}: {}", title, error)),
+            };
+            let _ = sender.send(Report {
+                message,
+                refresh: true,
+            });
+        });
+        Ok(())
+    }
+
+    /// Pastes a file when either it or the target directory is remote. Files are copied or
+    /// moved on the host when both are on the same one and transferred otherwise.
+    fn paste_remote(
+        &self,
+        mode: &str,
+        source_vfs: &dyn Vfs,
+        source: &Path,
+        target: &Path,
+    ) -> Result<(), Error> {
+        let Some(name) = source.file_name() else {
+            return Err(anyhow!("cannot paste {}", source.display()));
+        };
+        let destination = target.join(name);
+        let same_host = source_vfs.url(source) == self.vfs.url(source);
+        match (same_host, mode) {
+            (true, "cut") => self.vfs.rename(source, &destination)?,
+            (true, _) => self.vfs.copy(source, &destination)?,
+            (false, _) => {
+                vfs::transfer(source_vfs, source, self.vfs.as_ref(), &destination)?;
+                if mode == "cut" {
+                    source_vfs.remove(source)?;
                 }
-                self.clear_files();
-                self.refresh();
-            }
-        // When nothing is selected (in rare cases).
-        } else {
-            self.synchronize().expect("synchronization failed");
-            let current = &self.files.path;
-            for path in self.copied.iter() {
-                let mut child = process::Command::new("fm-paste")
-                    .arg("copy")
-                    .arg(path.clone())
-                    .arg(current.clone())
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
-            }
-            for path in self.cut.iter() {
-                let mut child = process::Command::new("fm-paste")
-                    .arg("cut")
-                    .arg(path.clone())
-                    .arg(current.clone())
-                    .spawn()
-                    .expect("failed to execute process");
-                child.wait().expect("child process failed");
             }
-            self.clear_files();
-            self.refresh();
         }
+        Ok(())
     }
 
-    pub fn clear(&self) {
-        // Clear the output buffer.
+    /// Dismisses the output pane.
+    pub fn clear(&mut self) {
+        self.output = None;
     }
 
     pub fn clear_files(&mut self) {
@@ -1120,6 +1855,7 @@ impl<'a> Application<'a> {
             cursor::MoveTo(0, 0),
             cursor::Show,
             DisableMouseCapture,
+            DisableBracketedPaste,
             Clear(ClearType::All),
         )
         .unwrap();
@@ -1132,7 +1868,8 @@ impl<'a> Application<'a> {
             cursor::MoveTo(0, 0),
             EnterAlternateScreen,
             cursor::Hide,
-            EnableMouseCapture
+            EnableMouseCapture,
+            EnableBracketedPaste
         )
         .unwrap();
         enable_raw_mode().expect("could not enable raw mode");
@@ -1141,11 +1878,21 @@ impl<'a> Application<'a> {
         let _ = self.set_title();
     }
 
-    pub fn trash(&mut self) {
+    pub fn trash(&mut self) -> Result<(), Error> {
+        self.check_local("trash")?;
         let marked = self.marked();
+        let trashed = if marked.is_empty() {
+            self.selected()
+                .map(|file| vec![file.path])
+                .unwrap_or_default()
+        } else {
+            marked.clone()
+        };
+        self.check_members("trash", &trashed)?;
         if marked.is_empty() {
             if let Some(selected) = self.selected() {
-                let mut child = process::Command::new("fm-trash")
+                let mut child = self
+                    .command("fm-trash")
                     .arg(format!("\"{}\"", selected.path.display()))
                     .spawn()
                     .expect("failed to execute process");
@@ -1161,44 +1908,144 @@ impl<'a> Application<'a> {
                     marked_str = format!("{} \"{}\"", marked_str, path.display());
                 }
             }
-            let mut child = process::Command::new("fm-trash")
+            let mut child = self
+                .command("fm-trash")
                 .arg(marked_str)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
             self.refresh();
         }
+        if !trashed.is_empty() {
+            self.hook("on_trash", &[("fm_trashed", join_paths(&trashed))]);
+        }
+        Ok(())
     }
 
-    pub fn preview(&mut self) {
+    pub fn preview(&mut self) -> Result<(), Error> {
+        self.check_local("preview")?;
         if let Some(selected) = self.selected() {
+            self.check_members("preview", &[selected.path.clone()])?;
             self.cmd_pre();
 
-            let mut child = process::Command::new("fm-preview")
+            let mut child = self
+                .command("fm-preview")
                 .arg(selected.path)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
             self.cmd_post();
         }
+        Ok(())
+    }
+
+    /// Opens the selected file with the first of its openers.
+    pub fn open(&mut self) -> Result<(), Error> {
+        self.check_local("open")?;
+        let Some(selected) = self.selected() else {
+            return Ok(());
+        };
+        self.check_members("open", &[selected.path.clone()])?;
+        match opener::openers(&self.configuration, &selected.path).first() {
+            Some(opener) => self.run_opener(opener, &[selected.path]),
+            None => Ok(()),
+        }
     }
 
-    pub fn open(&self) {
+    /// Shows the menu of all openers of the selected file.
+    pub fn open_with(&mut self) -> Result<(), Error> {
+        self.check_local("open-with")?;
         if let Some(selected) = self.selected() {
-            let mut child = process::Command::new("fm-open")
-                .arg(selected.path)
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
+            self.check_members("open-with", &[selected.path.clone()])?;
+            let openers = opener::openers(&self.configuration, &selected.path);
+            let mut state = ListState::default();
+            state.select(Some(0));
+            self.open_with = Some(OpenWith {
+                path: selected.path,
+                openers,
+                state,
+            });
         }
+        Ok(())
     }
 
-    pub fn rename(&mut self) {
-        self.cmd_pre();
+    /// Handles a key while the "Open with" menu is shown, openers can also be picked by number.
+    pub fn open_with_key(&mut self, key: KeyEvent) -> Result<(), Error> {
+        let Some(menu) = &mut self.open_with else {
+            return Ok(());
+        };
+        let last = menu.openers.len().saturating_sub(1);
+        let selected = menu.state.selected().unwrap_or_default();
+        let chosen = match key.code {
+            KeyCode::Up => {
+                menu.state.select(Some(selected.saturating_sub(1)));
+                None
+            }
+            KeyCode::Down => {
+                menu.state.select(Some((selected + 1).min(last)));
+                None
+            }
+            KeyCode::Enter => Some(selected),
+            KeyCode::Char(c) => match c.to_digit(10) {
+                Some(number @ 1..) if number as usize <= menu.openers.len() => {
+                    Some(number as usize - 1)
+                }
+                _ => None,
+            },
+            KeyCode::Esc => {
+                self.open_with = None;
+                None
+            }
+            _ => None,
+        };
+        if let Some(index) = chosen {
+            if let Some(menu) = self.open_with.take() {
+                return self.run_opener(&menu.openers[index], &[menu.path]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs an opener for files, in the terminal or in the background.
+    pub fn run_opener(&mut self, opener: &Opener, paths: &[PathBuf]) -> Result<(), Error> {
+        let quoted = paths
+            .iter()
+            .map(|path| commands::shell_quote(&path.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let command = if opener.command.contains("%f") {
+            commands::expand_placeholders(&opener.command, |placeholder| {
+                (placeholder == 'f').then(|| quoted.clone())
+            })
+        } else {
+            format!("{} {}", opener.command, quoted)
+        };
+        if opener.terminal {
+            self.run_shell(command, ShellMode::Interactive, true)?;
+        } else {
+            self.run_shell(command, ShellMode::Background, false)?;
+        }
+        let details = [
+            ("fm_opened", join_paths(paths)),
+            ("fm_opener", opener.name().to_owned()),
+        ];
+        self.hook("on_open", &details);
+        Ok(())
+    }
+
+    pub fn rename(&mut self) -> Result<(), Error> {
+        self.check_local("rename")?;
         let marked = self.marked();
+        let mut renamed = marked.clone();
+        if renamed.is_empty() {
+            renamed.extend(self.selected().map(|file| file.path));
+        }
+        self.check_members("rename", &renamed)?;
+        self.cmd_pre();
         if marked.is_empty() {
             if let Some(selected) = self.selected() {
-                let mut child = process::Command::new("fm-rename")
+                let mut child = self
+                    .command("fm-rename")
                     .arg(format!("\"{}\"", selected.path.to_string_lossy()))
                     .spawn()
                     .expect("failed to execute process");
@@ -1213,7 +2060,8 @@ impl<'a> Application<'a> {
                     marked_str = format!("{} \"{}\"", marked_str, path.to_string_lossy());
                 }
             }
-            let mut child = process::Command::new("fm-rename")
+            let mut child = self
+                .command("fm-rename")
                 .arg(marked_str)
                 .spawn()
                 .expect("failed to execute process");
@@ -1221,64 +2069,113 @@ impl<'a> Application<'a> {
         }
         self.cmd_post();
         self.refresh();
+        Ok(())
     }
 
-    pub fn edit(&mut self) {
-        if let Some(selected) = self.selected() {
-            self.cmd_pre();
-            let mut child = process::Command::new("vim")
-                .arg(selected.path)
-                .spawn()
-                .expect("failed to execute process");
-            child.wait().expect("child process failed");
-            self.cmd_post();
+    /// Edits the marked files, or the selected file when none are marked.
+    pub fn edit(&mut self) -> Result<(), Error> {
+        self.check_local("edit")?;
+        let files = self.edit_files();
+        self.check_members("edit", &files)?;
+        if files.is_empty() {
+            return Ok(());
         }
+        let mut editor = opener::editor();
+        let flags = self.edit_flags(files.len());
+        if !flags.is_empty() {
+            editor.command = format!("{} {}", editor.command, flags.join(" "));
+        }
+        self.run_opener(&editor, &files)
     }
 
-    pub fn editnox(&self) {
-        if let Some(selected) = self.selected() {
-            let mut child = process::Command::new("window-edit-no")
-                .arg(selected.path)
+    pub fn editnox(&self) -> Result<(), Error> {
+        self.check_local("edit-window-no")?;
+        let files = self.edit_files();
+        self.check_members("edit-window-no", &files)?;
+        if !files.is_empty() {
+            let mut child = self
+                .command("window-edit-no")
+                .args(self.edit_flags(files.len()))
+                .args(files)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
         }
+        Ok(())
     }
 
-    pub fn editx(&self) {
-        if let Some(selected) = self.selected() {
-            let mut child = process::Command::new("window-edit")
-                .arg(selected.path)
+    pub fn editx(&self) -> Result<(), Error> {
+        self.check_local("edit-window")?;
+        let files = self.edit_files();
+        self.check_members("edit-window", &files)?;
+        if !files.is_empty() {
+            let mut child = self
+                .command("window-edit")
+                .args(self.edit_flags(files.len()))
+                .args(files)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
         }
+        Ok(())
+    }
+
+    /// Returns the marked files which aren't directories, or the selected file when
+    /// none are marked.
+    fn edit_files(&self) -> Vec<PathBuf> {
+        let mut marked: Vec<PathBuf> = self
+            .marked
+            .iter()
+            .filter(|path| !path.is_dir())
+            .cloned()
+            .collect();
+        marked.sort();
+        if !marked.is_empty() {
+            return marked;
+        }
+        self.selected()
+            .map(|file| vec![file.path])
+            .unwrap_or_default()
+    }
+
+    /// Editor flags for the number of files, the configured `edit_tabs` when there are several.
+    fn edit_flags(&self, count: usize) -> Vec<&str> {
+        if count > 1 {
+            self.configuration.edit_tabs.split_whitespace().collect()
+        } else {
+            vec![]
+        }
     }
 
     pub fn editx_path(&self, path: PathBuf) {
-        let mut child = process::Command::new("window-edit")
+        let mut child = self
+            .command("window-edit")
             .arg(path)
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
     }
 
-    pub fn file_manager(&self) {
+    pub fn file_manager(&self) -> Result<(), Error> {
+        self.check_local("file-manager")?;
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("directory.default")
+            if selected.member.is_none() && selected.is_dir() {
+                let mut child = self
+                    .command("directory.default")
                     .arg(selected.path.clone())
                     .spawn()
                     .expect("failed to execute process");
                 child.wait().expect("child process failed");
             } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("directory.default")
+                let mut child = self
+                    .command("directory.default")
                     .arg(parent)
                     .spawn()
                     .expect("failed to execute process");
                 child.wait().expect("child process failed");
             }
         }
+        Ok(())
     }
 
     pub fn shell(&mut self) {
@@ -1287,41 +2184,50 @@ impl<'a> Application<'a> {
             .backend_mut()
             .execute(LeaveAlternateScreen)
             .expect("could not leave alternate screen");
-        let mut child = process::Command::new("fm-shell")
+        let mut child = self
+            .command("fm-shell")
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
         self.cmd_post();
     }
 
-    pub fn shellx(&self) {
+    pub fn shellx(&self) -> Result<(), Error> {
+        self.check_local("shell-window")?;
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
-                let mut child = process::Command::new("fm-shellx")
+            if selected.member.is_none() && selected.is_dir() {
+                let mut child = self
+                    .command("fm-shellx")
                     .arg(selected.path.clone())
                     .spawn()
                     .expect("failed to execute process");
                 child.wait().expect("child process failed");
             } else if let Some(parent) = selected.path.parent() {
-                let mut child = process::Command::new("fm-shellx")
+                let mut child = self
+                    .command("fm-shellx")
                     .arg(parent)
                     .spawn()
                     .expect("failed to execute process");
                 child.wait().expect("child process failed");
             }
         }
+        Ok(())
     }
 
-    pub fn shellx_root(&self) {
-        let mut child = process::Command::new("fm-shellx")
+    pub fn shellx_root(&self) -> Result<(), Error> {
+        self.check_local("shell-window-root")?;
+        let mut child = self
+            .command("fm-shellx")
             .arg(self.files.path.clone())
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
+        Ok(())
     }
 
     pub fn images(&self) {
-        let mut child = process::Command::new("fm-images")
+        let mut child = self
+            .command("fm-images")
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
@@ -1358,9 +2264,29 @@ impl<'a> Application<'a> {
         self.refresh();
     }
 
+    /// Sorts by a key from [`config::SORT_KEYS`], in reverse if the only option is `reverse`.
+    pub fn sort(&mut self, key: &str, options: &[String]) -> Result<(), Error> {
+        let reverse = match options {
+            [] => false,
+            [option] if option == "reverse" => true,
+            _ => return Err(anyhow!("usage: sort <key> [reverse]")),
+        };
+        self.configuration.set_key("sort.by", key)?;
+        self.configuration.sort.reverse = reverse;
+        self.refresh();
+        Ok(())
+    }
+
+    pub fn toggle_preview(&mut self) {
+        self.configuration.preview = !self.configuration.preview;
+        self.preview.invalidate();
+    }
+
     pub fn search_all(&mut self) -> Result<(), Error> {
+        self.check_local("search-all")?;
         self.cmd_pre();
-        let mut child = process::Command::new("fm-search-all")
+        let mut child = self
+            .command("fm-search-all")
             .arg(self.files.path.clone())
             .spawn()?;
         child.wait()?;
@@ -1378,29 +2304,37 @@ impl<'a> Application<'a> {
         Ok(())
     }
 
-    pub fn vscode(&self) {
+    pub fn vscode(&self) -> Result<(), Error> {
+        self.check_local("vscode")?;
         if let Some(selected) = self.selected() {
-            let mut child = process::Command::new("vscode.default")
+            let mut child = self
+                .command("vscode.default")
                 .arg(selected.path)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
         }
+        Ok(())
     }
 
-    pub fn vscode_root(&self) {
-        let mut child = process::Command::new("vscode.default")
+    pub fn vscode_root(&self) -> Result<(), Error> {
+        self.check_local("vscode-root")?;
+        let mut child = self
+            .command("vscode.default")
             .arg(self.files.path.clone())
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
+        Ok(())
     }
 
-    pub fn drag_and_drop(&self) {
+    pub fn drag_and_drop(&self) -> Result<(), Error> {
+        self.check_local("drag-and-drop")?;
         let marked = self.marked();
         if marked.is_empty() {
             if let Some(selected) = self.selected() {
-                let mut child = process::Command::new("fm-drag-and-drop")
+                let mut child = self
+                    .command("fm-drag-and-drop")
                     .arg(selected.path)
                     .spawn()
                     .expect("failed to execute process");
@@ -1415,124 +2349,206 @@ impl<'a> Application<'a> {
                     marked_str = format!("{} \"{}\"", marked_str, path.to_string_lossy());
                 }
             }
-            let mut child = process::Command::new("fm-drag-and-drop")
+            let mut child = self
+                .command("fm-drag-and-drop")
                 .arg(marked_str)
                 .spawn()
                 .expect("failed to execute process");
             child.wait().expect("child process failed");
         }
+        Ok(())
     }
 
     pub fn git_log(&mut self) {
         self.cmd_pre();
-        let mut child = process::Command::new("fm-git-log")
+        let mut child = self
+            .command("fm-git-log")
             .spawn()
             .expect("failed to execute process");
         child.wait().expect("child process failed");
         self.cmd_post();
     }
 
-    pub fn read_dir(dir: PathBuf, show_hidden: bool) -> Result<File> {
-        let metadata = fs::metadata(&dir)?;
-        let metadata_extra = fs::symlink_metadata(&dir)?;
+    pub fn read_dir(vfs: &dyn Vfs, dir: PathBuf, show_hidden: bool, sort: &Sort) -> Result<File> {
+        let stat = vfs.stat(&dir)?;
         let mut descendants: Vec<File> = Vec::new();
 
-        for entry in fs::read_dir(&dir)? {
-            let entry = entry?;
+        for path in vfs.list(&dir)? {
             if !show_hidden {
-                if let Some(name) = entry.path().file_name() {
+                if let Some(name) = path.file_name() {
                     if name.to_string_lossy().starts_with('.') {
                         continue;
                     }
                 }
             }
 
-            let metadata = match fs::metadata(entry.path()) {
-                Err(error) => match error.kind() {
-                    ErrorKind::NotFound => {
-                        error!("could not read file metadata: {}", entry.path().display());
-                        continue;
-                    }
-                    _ => return Err(error.into()),
-                },
-                Ok(metadata) => metadata,
-            };
-            let metadata_extra = match fs::symlink_metadata(entry.path()) {
+            let stat = match vfs.stat(&path) {
                 Err(error) => match error.kind() {
                     ErrorKind::NotFound => {
-                        error!(
-                            "could not read file symlink metadata: {}",
-                            entry.path().display()
-                        );
+                        error!("could not read file metadata: {}", path.display());
                         continue;
                     }
                     _ => return Err(error.into()),
                 },
-                Ok(metadata) => metadata,
+                Ok(stat) => stat,
             };
 
             let descendant = File {
-                path: entry.path(),
-                metadata,
-                metadata_extra,
+                path,
+                stat,
                 descendants: vec![],
+                member: None,
             };
             descendants.push(descendant);
         }
-        descendants.sort();
+        Application::sort_files(vfs, &dir, &mut descendants, sort, &mut GitRanks::default());
 
         Ok(File {
             path: dir,
-            metadata,
-            metadata_extra,
+            stat,
             descendants,
+            member: None,
         })
     }
 
     pub fn read_tree(&self, dir: PathBuf) -> Result<File, Error> {
-        let metadata = fs::metadata(&dir)?;
-        let metadata_extra = fs::symlink_metadata(&dir)?;
+        self.read_tree_ranked(dir, &mut GitRanks::default())
+    }
+
+    /// Reads a directory and its expanded subdirectories, sharing the git statuses of their
+    /// repositories.
+    fn read_tree_ranked(&self, dir: PathBuf, ranks: &mut GitRanks) -> Result<File, Error> {
+        let stat = self.vfs.stat(&dir)?;
         let mut descendants: Vec<File> = Vec::new();
 
-        for entry in fs::read_dir(&dir)? {
-            let entry = entry?;
+        for path in self.vfs.list(&dir)? {
             if !self.configuration.show_hidden {
-                if let Some(name) = entry.path().file_name() {
+                if let Some(name) = path.file_name() {
                     if name.to_string_lossy().starts_with('.') {
                         continue;
                     }
                 }
             }
-            let metadata = fs::metadata(entry.path())?;
-            let metadata_extra = fs::symlink_metadata(entry.path())?;
+            let stat = self.vfs.stat(&path)?;
 
-            let descendant = if metadata.is_dir() && self.expanded.contains(&entry.path()) {
-                self.read_tree(entry.path())?
+            let descendant = if stat.is_dir && self.expanded.contains(&path) {
+                self.read_tree_ranked(path, ranks)?
             } else {
-                File {
-                    path: entry.path(),
-                    metadata,
-                    metadata_extra,
+                let mut file = File {
+                    path,
+                    stat,
                     descendants: vec![],
+                    member: None,
+                };
+                // An archive which can't be read any more is shown collapsed.
+                if file.is_archive() && self.expanded.contains(&file.path) {
+                    let descendants = self.read_archive(&file).ok().flatten();
+                    file.descendants = descendants.unwrap_or_default();
                 }
+                file
             };
             descendants.push(descendant);
         }
-        descendants.sort();
+        let sort = &self.configuration.sort;
+        Application::sort_files(self.vfs.as_ref(), &dir, &mut descendants, sort, ranks);
 
         Ok(File {
             path: dir,
-            metadata,
-            metadata_extra,
+            stat,
             descendants,
+            member: None,
         })
     }
 
+    /// Sorts the files listed in a directory, git statuses are only read for local ones.
+    fn sort_files(
+        vfs: &dyn Vfs,
+        dir: &Path,
+        files: &mut [File],
+        sort: &Sort,
+        ranks: &mut GitRanks,
+    ) {
+        if sort.by == SortKey::Git && vfs.url(dir).is_none() {
+            files::sort(files, sort, ranks.get(dir));
+        } else {
+            files::sort(files, sort, &HashMap::new());
+        }
+    }
+
+    /// Lists the members of an archive, or of a directory inside one, as virtual files.
+    /// Expanded directories of the archive are listed as well. Returns nothing while the
+    /// archive is listed in the background, the tree is refreshed once it is listed.
+    fn read_archive(&self, file: &File) -> Result<Option<Vec<File>>, Error> {
+        let (path, name) = match &file.member {
+            Some(member) => (member.archive.clone(), member.name.clone()),
+            None => (file.path.clone(), PathBuf::new()),
+        };
+        if self.vfs.url(&path).is_some() {
+            return Err(anyhow!(
+                "archives can only be browsed on the local filesystem"
+            ));
+        }
+        let sender = self.jobs_sender.clone();
+        let title = path.display().to_string();
+        let members = archive::list_in_background(&path, move |count| {
+            let message = match count {
+                Ok(count) => Message::Info(format!("{}: {} members", title, count)),
+                Err(error) => Message::Error(format!("{}: {}", title, error)),
+            };
+            let _ = sender.send(Report {
+                message,
+                refresh: true,
+            });
+        });
+        let Some(members) = members else {
+            return Ok(None);
+        };
+        Ok(Some(self.archive_children(file, &members?, &name)))
+    }
+
+    fn archive_children(&self, parent: &File, members: &[Member], dir: &Path) -> Vec<File> {
+        let mut children: Vec<File> = members
+            .iter()
+            .filter(|member| member.name.parent() == Some(dir))
+            .filter(|member| {
+                let name = member.name.file_name().unwrap_or_default();
+                self.configuration.show_hidden || !name.to_string_lossy().starts_with('.')
+            })
+            .map(|member| {
+                let mut child = File {
+                    path: member.path(),
+                    stat: Stat {
+                        is_dir: member.is_dir,
+                        is_file: !member.is_dir,
+                        len: member.size,
+                        modified: parent.stat.modified,
+                        changed: parent.stat.changed,
+                        ..Stat::default()
+                    },
+                    descendants: vec![],
+                    member: Some(member.clone()),
+                };
+                if member.is_dir && self.expanded.contains(&child.path) {
+                    child.descendants = self.archive_children(&child, members, &member.name);
+                }
+                child
+            })
+            .collect();
+        files::sort(&mut children, &self.configuration.sort, &HashMap::new());
+        children
+    }
+
     pub fn quit(&mut self) -> Result<(), Error> {
+        self.save_view();
+        if let Err(error) = self.save_session(LAST_SESSION) {
+            error!("could not save the session: {}", error);
+        }
+        self.hook("on_quit", &[]);
         disable_raw_mode()?;
         execute!(
             self.terminal.backend_mut(),
             DisableMouseCapture,
+            DisableBracketedPaste,
             ResetColor,
             LeaveAlternateScreen,
             cursor::Show,
@@ -1541,10 +2557,16 @@ impl<'a> Application<'a> {
     }
 
     pub fn quit_and_print(&mut self, output_path: String, paths: Vec<String>) -> Result<(), Error> {
+        self.save_view();
+        if let Err(error) = self.save_session(LAST_SESSION) {
+            error!("could not save the session: {}", error);
+        }
+        self.hook("on_quit", &[]);
         disable_raw_mode()?;
         execute!(
             self.terminal.backend_mut(),
             DisableMouseCapture,
+            DisableBracketedPaste,
             ResetColor,
             LeaveAlternateScreen,
             cursor::Show,
@@ -1564,9 +2586,19 @@ impl<'a> Application<'a> {
         self.quit()
     }
 
+    /// Writes the chosen paths for the file chooser and quits.
+    pub fn choose(&mut self) -> Result<(), Error> {
+        match self.file_chooser.clone() {
+            Some(FileChooser::Dir(output_path)) => self.quit_print_dir(output_path),
+            Some(FileChooser::Single(output_path)) => self.quit_print_file(output_path),
+            Some(FileChooser::Multiple(output_path)) => self.quit_print_marked(output_path),
+            None => Err(anyhow!("not in file chooser mode")),
+        }
+    }
+
     pub fn quit_print_dir(&mut self, output_path: String) -> Result<(), Error> {
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_dir() {
+            if selected.member.is_none() && selected.is_dir() {
                 self.quit_and_print(output_path, vec![selected.path.to_string_lossy().into()])?;
             }
         }
@@ -1588,7 +2620,7 @@ impl<'a> Application<'a> {
 
     pub fn quit_print_file(&mut self, output_path: String) -> Result<(), Error> {
         if let Some(selected) = self.selected() {
-            if selected.metadata.is_file() {
+            if selected.member.is_none() && selected.stat.is_file {
                 self.quit_and_print(output_path, vec![selected.path.to_string_lossy().into()]);
             }
         }
@@ -1614,6 +2646,30 @@ impl<'a> Application<'a> {
     }
 }
 
+/// Returns the height of the output pane including its title, a third of the terminal.
+fn output_pane_height(height: u16) -> u16 {
+    (height.saturating_sub(2) / 3)
+        .max(3)
+        .min(height.saturating_sub(2))
+}
+
+/// Splits the area between the tree and the preview pane.
+fn tree_and_preview(area: Rect) -> Vec<Rect> {
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(area)
+}
+
+fn join_paths<'p>(paths: impl IntoIterator<Item = &'p PathBuf>) -> String {
+    let mut paths: Vec<_> = paths
+        .into_iter()
+        .map(|path| path.to_string_lossy())
+        .collect();
+    paths.sort();
+    paths.join("\n")
+}
+
 fn find_target_file<'a>(
     file: &'a mut File,
     current: &mut usize,
@@ -0,0 +1,746 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use std::time::SystemTime;
+use std::{fmt, fs};
+
+use anyhow::{bail, Error, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use walkdir::WalkDir;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use zip::write::SimpleFileOptions;
+
+/// Archive formats whose contents can be browsed in the tree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    Zip,
+}
+
+/// File name suffixes of the archive formats.
+const SUFFIXES: &[(&str, Kind)] = &[
+    (".tar", Kind::Tar),
+    (".tar.gz", Kind::TarGz),
+    (".tgz", Kind::TarGz),
+    (".tar.xz", Kind::TarXz),
+    (".txz", Kind::TarXz),
+    (".tar.zst", Kind::TarZst),
+    (".tzst", Kind::TarZst),
+    (".zip", Kind::Zip),
+];
+
+/// Members of the archives listed so far, keyed by the path of the archive.
+static LISTINGS: OnceLock<Mutex<HashMap<PathBuf, Listing>>> = OnceLock::new();
+
+/// Members of an archive as of its modification time.
+struct Listing {
+    modified: SystemTime,
+    /// The members or the error listing them, none while they are listed in the background.
+    members: Option<Result<Arc<[Member]>, String>>,
+}
+
+/// Members which were skipped when extracting an archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Skipped {
+    /// Members with absolute paths or `..` components.
+    pub unsafe_paths: usize,
+    /// Symbolic and hard links, which are never extracted.
+    pub links: usize,
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+        if self.unsafe_paths > 0 {
+            parts.push(format!("{} members with unsafe paths", self.unsafe_paths));
+        }
+        if self.links > 0 {
+            parts.push(format!("{} links", self.links));
+        }
+        write!(f, "{}", parts.join(" and "))
+    }
+}
+
+/// A file or directory inside an archive, shown in the tree as a virtual file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    /// Path of the archive on disk.
+    pub archive: PathBuf,
+    /// Relative path of the member inside the archive.
+    pub name: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+impl Member {
+    /// Returns the virtual path of the member below the path of its archive.
+    pub fn path(&self) -> PathBuf {
+        self.archive.join(&self.name)
+    }
+}
+
+/// Returns the format of an archive from its file name.
+pub fn kind(path: &Path) -> Option<Kind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.len() > suffix.len() && name.ends_with(suffix))
+        .map(|(_, kind)| *kind)
+}
+
+/// Splits the virtual path of a member into the path of its archive and the name inside it.
+pub fn split(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| kind(ancestor).is_some() && ancestor.is_file())?;
+    let name = path.strip_prefix(archive).ok()?.to_path_buf();
+    Some((archive.to_path_buf(), name))
+}
+
+/// Lists all members of an archive sorted by name, including directories which are only
+/// implied by the paths of their files.
+pub fn members(archive: &Path) -> Result<Vec<Member>, Error> {
+    let mut members = BTreeMap::new();
+    visit(archive, |entry| {
+        let Entry {
+            name, is_dir, size, ..
+        } = entry;
+        for parent in name.ancestors().skip(1) {
+            if parent.as_os_str().is_empty() {
+                break;
+            }
+            members.entry(parent.to_path_buf()).or_insert(Member {
+                archive: archive.to_path_buf(),
+                name: parent.to_path_buf(),
+                is_dir: true,
+                size: 0,
+            });
+        }
+        members.insert(
+            name.clone(),
+            Member {
+                archive: archive.to_path_buf(),
+                name,
+                is_dir,
+                size,
+            },
+        );
+        Ok(true)
+    })?;
+    Ok(members.into_values().collect())
+}
+
+/// Returns the members of an archive like `members`, listed again only once it is modified.
+pub fn cached(archive: &Path) -> Result<Arc<[Member]>, Error> {
+    let modified = fs::metadata(archive)?.modified()?;
+    if let Some(Listing {
+        members: Some(members),
+        ..
+    }) = listings()
+        .get(archive)
+        .filter(|listing| listing.modified == modified)
+    {
+        return members.clone().map_err(Error::msg);
+    }
+    let members = list(archive, modified);
+    members.clone().map_err(Error::msg)
+}
+
+/// Returns the members of an archive if they were listed since it was last modified.
+/// Otherwise they are listed in the background, and `done` is called with their number.
+pub fn list_in_background<F>(archive: &Path, done: F) -> Option<Result<Arc<[Member]>, Error>>
+where
+    F: FnOnce(Result<usize, Error>) + Send + 'static,
+{
+    let modified = match fs::metadata(archive).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(error) => return Some(Err(error.into())),
+    };
+    let mut listings = listings();
+    if let Some(listing) = listings
+        .get(archive)
+        .filter(|listing| listing.modified == modified)
+    {
+        // The archive is still being listed if there are no members yet.
+        return listing
+            .members
+            .clone()
+            .map(|members| members.map_err(Error::msg));
+    }
+    listings.insert(
+        archive.to_path_buf(),
+        Listing {
+            modified,
+            members: None,
+        },
+    );
+    let archive = archive.to_path_buf();
+    thread::spawn(move || {
+        let members = list(&archive, modified);
+        done(members.map(|members| members.len()).map_err(Error::msg));
+    });
+    None
+}
+
+fn list(archive: &Path, modified: SystemTime) -> Result<Arc<[Member]>, String> {
+    let members = members(archive)
+        .map(Arc::from)
+        .map_err(|error| error.to_string());
+    let listing = Listing {
+        modified,
+        members: Some(members.clone()),
+    };
+    listings().insert(archive.to_path_buf(), listing);
+    members
+}
+
+fn listings() -> MutexGuard<'static, HashMap<PathBuf, Listing>> {
+    LISTINGS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reads up to `limit` bytes from the start of a member.
+pub fn read(archive: &Path, member: &Path, limit: u64) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    visit(archive, |entry| {
+        if entry.name != member {
+            return Ok(true);
+        }
+        entry.reader.take(limit).read_to_end(&mut bytes)?;
+        Ok(false)
+    })?;
+    Ok(bytes)
+}
+
+/// Extracts a member and everything below it into a directory and returns the extracted path.
+pub fn extract(archive: &Path, member: &Path, target: &Path) -> Result<PathBuf, Error> {
+    let Some(file_name) = member.file_name() else {
+        bail!("invalid archive member: {}", member.display());
+    };
+    let destination = target.join(file_name);
+    if destination.exists() {
+        bail!("{} already exists", destination.display());
+    }
+    let mut modes = vec![];
+    visit(archive, |entry| {
+        let Ok(relative) = entry.name.strip_prefix(member) else {
+            return Ok(true);
+        };
+        let path = if relative.as_os_str().is_empty() {
+            destination.clone()
+        } else {
+            destination.join(relative)
+        };
+        write_entry(entry, &path, &mut modes)?;
+        Ok(true)
+    })?;
+    set_modes(modes)?;
+    if !destination.exists() {
+        bail!("{} not found in {}", member.display(), archive.display());
+    }
+    Ok(destination)
+}
+
+/// Returns the directory an archive is extracted to, named after the archive without its
+/// suffix e.g. `src.tar.gz` is extracted to `src`.
+pub fn extract_dir(archive: &Path) -> Option<PathBuf> {
+    let name = archive.file_name()?.to_string_lossy();
+    let (suffix, _) = SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.to_lowercase().ends_with(suffix))?;
+    let stem = &name[..name.len() - suffix.len()];
+    (!stem.is_empty()).then(|| archive.with_file_name(stem))
+}
+
+/// Extracts a whole archive into a new directory, calling `progress` with the number of
+/// entries extracted so far. Members with absolute paths or `..` components and links are
+/// skipped and counted.
+pub fn extract_all<F>(archive: &Path, target: &Path, mut progress: F) -> Result<Skipped, Error>
+where
+    F: FnMut(usize),
+{
+    if target.exists() {
+        bail!("{} already exists", target.display());
+    }
+    fs::create_dir(target)?;
+    let mut count = 0;
+    let mut modes = vec![];
+    let skipped = visit(archive, |entry| {
+        let path = target.join(&entry.name);
+        write_entry(entry, &path, &mut modes)?;
+        count += 1;
+        progress(count);
+        Ok(true)
+    })?;
+    set_modes(modes)?;
+    Ok(skipped)
+}
+
+/// Creates an archive of files and directories, in the format given by its suffix, calling
+/// `progress` with the number of entries written and the total. Entries are named relative to
+/// the directory containing each path. The archive is removed again if writing it fails.
+pub fn create<F>(archive: &Path, paths: &[PathBuf], mut progress: F) -> Result<(), Error>
+where
+    F: FnMut(usize, usize),
+{
+    let Some(kind) = kind(archive) else {
+        bail!("unknown archive format: {}", archive.display());
+    };
+    let mut entries = vec![];
+    for path in paths {
+        let base = path.parent().unwrap_or(Path::new(""));
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry?;
+            if entry.path() == archive {
+                continue;
+            }
+            let name = entry.path().strip_prefix(base)?.to_path_buf();
+            entries.push((entry.into_path(), name));
+        }
+    }
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(archive)?;
+    let result = match kind {
+        Kind::Tar => write_tar(file, &entries, &mut progress).map(drop),
+        Kind::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+            write_tar(encoder, &entries, &mut progress)
+                .and_then(|encoder| Ok(encoder.finish()?))
+                .map(drop)
+        }
+        Kind::TarXz => {
+            let encoder = XzEncoder::new(file, 6);
+            write_tar(encoder, &entries, &mut progress)
+                .and_then(|encoder| Ok(encoder.finish()?))
+                .map(drop)
+        }
+        Kind::TarZst => zstd::Encoder::new(file, 0)
+            .map_err(Error::from)
+            .and_then(|encoder| write_tar(encoder, &entries, &mut progress))
+            .and_then(|encoder| Ok(encoder.finish()?))
+            .map(drop),
+        Kind::Zip => write_zip(file, &entries, &mut progress),
+    };
+    if result.is_err() {
+        let _ = fs::remove_file(archive);
+    }
+    result
+}
+
+fn write_tar<W, F>(writer: W, entries: &[(PathBuf, PathBuf)], progress: &mut F) -> Result<W, Error>
+where
+    W: Write,
+    F: FnMut(usize, usize),
+{
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    for (index, (path, name)) in entries.iter().enumerate() {
+        builder.append_path_with_name(path, name)?;
+        progress(index + 1, entries.len());
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn write_zip<F>(
+    file: fs::File,
+    entries: &[(PathBuf, PathBuf)],
+    progress: &mut F,
+) -> Result<(), Error>
+where
+    F: FnMut(usize, usize),
+{
+    let mut zip = zip::ZipWriter::new(file);
+    for (index, (path, name)) in entries.iter().enumerate() {
+        let metadata = fs::symlink_metadata(path)?;
+        let options = SimpleFileOptions::default().unix_permissions(metadata.permissions().mode());
+        let name = name.to_string_lossy();
+        if metadata.is_symlink() {
+            let target = fs::read_link(path)?;
+            zip.add_symlink(name, target.to_string_lossy(), options)?;
+        } else if metadata.is_dir() {
+            zip.add_directory(name, options)?;
+        } else {
+            zip.start_file(name, options)?;
+            io::copy(&mut fs::File::open(path)?, &mut zip)?;
+        }
+        progress(index + 1, entries.len());
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// A regular file or directory read from an archive.
+struct Entry<'r> {
+    name: PathBuf,
+    is_dir: bool,
+    size: u64,
+    /// Unix permissions if the archive stores them.
+    mode: Option<u32>,
+    reader: &'r mut dyn Read,
+}
+
+/// Writes a file or directory read from an archive to a path. Directories are writable until
+/// their files are extracted, their modes are added to `modes` to be set with `set_modes`.
+fn write_entry(entry: Entry, path: &Path, modes: &mut Vec<(PathBuf, u32)>) -> Result<(), Error> {
+    let create_dir = |path: &Path| {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o755)
+            .create(path)
+    };
+    if entry.is_dir {
+        create_dir(path)?;
+        modes.extend(entry.mode.map(|mode| (path.to_path_buf(), mode)));
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        create_dir(parent)?;
+    }
+    io::copy(entry.reader, &mut fs::File::create(path)?)?;
+    if let Some(mode) = entry.mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+/// Sets the modes of extracted directories, those inside others first so that a parent which
+/// can't be entered any more doesn't hide them.
+fn set_modes(mut modes: Vec<(PathBuf, u32)>) -> Result<(), Error> {
+    modes.sort_by(|(a, _), (b, _)| b.cmp(a));
+    for (path, mode) in modes {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+/// Calls a function with each regular file and directory of an archive until it returns
+/// `false`. Links and names which would leave the archive are skipped and counted.
+fn visit<F>(archive: &Path, mut f: F) -> Result<Skipped, Error>
+where
+    F: FnMut(Entry) -> Result<bool, Error>,
+{
+    let Some(kind) = kind(archive) else {
+        bail!("not an archive: {}", archive.display());
+    };
+    let file = BufReader::new(fs::File::open(archive)?);
+    match kind {
+        Kind::Tar => visit_tar(file, f),
+        Kind::TarGz => visit_tar(GzDecoder::new(file), f),
+        Kind::TarXz => visit_tar(XzDecoder::new(file), f),
+        Kind::TarZst => visit_tar(zstd::Decoder::with_buffer(file)?, f),
+        Kind::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            let mut skipped = Skipped::default();
+            for index in 0..zip.len() {
+                let mut file = zip.by_index(index)?;
+                if file.is_symlink() {
+                    skipped.links += 1;
+                    continue;
+                }
+                let Some(name) = file.enclosed_name().and_then(|name| normalize(&name)) else {
+                    skipped.unsafe_paths += 1;
+                    continue;
+                };
+                let entry = Entry {
+                    name,
+                    is_dir: file.is_dir(),
+                    size: file.size(),
+                    mode: file.unix_mode(),
+                    reader: &mut file,
+                };
+                if !f(entry)? {
+                    break;
+                }
+            }
+            Ok(skipped)
+        }
+    }
+}
+
+fn visit_tar<R, F>(reader: R, mut f: F) -> Result<Skipped, Error>
+where
+    R: Read,
+    F: FnMut(Entry) -> Result<bool, Error>,
+{
+    let mut tar = tar::Archive::new(reader);
+    let mut skipped = Skipped::default();
+    for entry in tar.entries()? {
+        let mut file = entry?;
+        let entry_type = file.header().entry_type();
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            skipped.links += 1;
+            continue;
+        }
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            continue;
+        }
+        let Some(name) = normalize(&file.path()?) else {
+            skipped.unsafe_paths += 1;
+            continue;
+        };
+        let entry = Entry {
+            name,
+            is_dir: entry_type.is_dir(),
+            size: file.size(),
+            mode: file.header().mode().ok(),
+            reader: &mut file,
+        };
+        if !f(entry)? {
+            break;
+        }
+    }
+    Ok(skipped)
+}
+
+/// Removes `.` components and rejects absolute paths and `..` so members stay inside the
+/// directory they are extracted to.
+pub fn normalize(name: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn write_tar_gz(path: &Path) {
+        let encoder = flate2::write::GzEncoder::new(
+            fs::File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (name, contents) in [("src/main.rs", "fn main() {}\n"), ("README", "hello\n")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, contents.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn write_zip(path: &Path) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_directory("docs/", options).unwrap();
+        zip.start_file("docs/a.txt", options).unwrap();
+        zip.write_all(b"a\n").unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_kind() {
+        assert_eq!(kind(Path::new("a.tar.gz")), Some(Kind::TarGz));
+        assert_eq!(kind(Path::new("A.ZIP")), Some(Kind::Zip));
+        assert_eq!(kind(Path::new("a.txz")), Some(Kind::TarXz));
+        assert_eq!(kind(Path::new("a.tar.zst")), Some(Kind::TarZst));
+        assert_eq!(kind(Path::new("a.gz")), None);
+        assert_eq!(kind(Path::new(".zip")), None);
+        assert_eq!(normalize(Path::new("./a/b")), Some(PathBuf::from("a/b")));
+        assert_eq!(normalize(Path::new("../a")), None);
+    }
+
+    #[test]
+    fn test_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let tar_gz = dir.join("a.tar.gz");
+        write_tar_gz(&tar_gz);
+        let zip = dir.join("b.zip");
+        write_zip(&zip);
+
+        let names = |archive: &Path| -> Vec<(String, bool)> {
+            members(archive)
+                .unwrap()
+                .into_iter()
+                .map(|member| (member.name.to_string_lossy().into_owned(), member.is_dir))
+                .collect()
+        };
+        assert_eq!(
+            names(&tar_gz),
+            vec![
+                ("README".into(), false),
+                ("src".into(), true),
+                ("src/main.rs".into(), false),
+            ]
+        );
+        assert_eq!(
+            names(&zip),
+            vec![("docs".into(), true), ("docs/a.txt".into(), false)]
+        );
+        assert_eq!(
+            split(&tar_gz.join("src/main.rs")),
+            Some((tar_gz.clone(), PathBuf::from("src/main.rs")))
+        );
+        assert_eq!(split(&tar_gz), None);
+        assert_eq!(read(&tar_gz, Path::new("README"), 3).unwrap(), b"hel");
+
+        // Listings are kept until the archive is modified.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        assert!(
+            list_in_background(&zip, move |count| sender.send(count.unwrap()).unwrap()).is_none()
+        );
+        assert_eq!(receiver.recv().unwrap(), 2);
+        let listed = list_in_background(&zip, |_| {}).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&listed, &cached(&zip).unwrap()));
+        let file = fs::File::options().write(true).open(&zip).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert!(!Arc::ptr_eq(&listed, &cached(&zip).unwrap()));
+
+        let out = dir.join("out");
+        fs::create_dir(&out).unwrap();
+        assert_eq!(
+            extract(&tar_gz, Path::new("src"), &out).unwrap(),
+            out.join("src")
+        );
+        assert_eq!(
+            fs::read_to_string(out.join("src/main.rs")).unwrap(),
+            "fn main() {}\n"
+        );
+        assert!(extract(&tar_gz, Path::new("src"), &out).is_err());
+        extract(&zip, Path::new("docs/a.txt"), &out).unwrap();
+        assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn test_extract_dir() {
+        assert_eq!(
+            extract_dir(Path::new("/a/src.tar.gz")),
+            Some(PathBuf::from("/a/src"))
+        );
+        assert_eq!(
+            extract_dir(Path::new("/a/Docs.ZIP")),
+            Some(PathBuf::from("/a/Docs"))
+        );
+        assert_eq!(extract_dir(Path::new("/a/notes.txt")), None);
+    }
+
+    #[test]
+    fn test_create_extract_all() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("src/bin")).unwrap();
+        fs::write(dir.join("src/bin/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("README"), "hello\n").unwrap();
+        fs::set_permissions(dir.join("README"), fs::Permissions::from_mode(0o755)).unwrap();
+        let paths = [dir.join("src"), dir.join("README")];
+
+        for name in ["a.tar.gz", "a.tar.zst", "a.tar.xz", "a.zip"] {
+            let archive = dir.join(name);
+            let mut written = 0;
+            create(&archive, &paths, |count, total| {
+                written = count;
+                assert_eq!(total, 4);
+            })
+            .unwrap();
+            assert_eq!(written, 4);
+            assert!(create(&archive, &paths, |_, _| {}).is_err());
+
+            let target = dir.join(format!("out-{name}"));
+            assert_eq!(
+                extract_all(&archive, &target, |_| {}).unwrap(),
+                Skipped::default()
+            );
+            assert_eq!(
+                fs::read_to_string(target.join("src/bin/main.rs")).unwrap(),
+                "fn main() {}\n"
+            );
+            let mode = fs::metadata(target.join("README"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+            assert!(extract_all(&archive, &target, |_| {}).is_err());
+        }
+    }
+
+    #[test]
+    fn test_extract_all_traversal() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let archive = dir.join("evil.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        for name in ["../escaped", "/tmp/absolute", "./ok"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(1);
+            header.set_mode(0o644);
+            // `append_data` refuses these names, so they are written into the header directly.
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_cksum();
+            builder.append(&header, &b"x"[..]).unwrap();
+        }
+        for entry_type in [tar::EntryType::Symlink, tar::EntryType::Link] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            header.set_cksum();
+            builder
+                .append_link(&mut header, "link", "/etc/passwd")
+                .unwrap();
+        }
+        // A read-only directory gets its mode after its files are extracted.
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o555);
+        builder
+            .append_data(&mut header, "readonly", io::empty())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(1);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "readonly/file", &b"x"[..])
+            .unwrap();
+        builder.finish().unwrap();
+
+        let target = dir.join("out");
+        let skipped = extract_all(&archive, &target, |_| {}).unwrap();
+        assert_eq!(
+            skipped,
+            Skipped {
+                unsafe_paths: 2,
+                links: 2
+            }
+        );
+        assert_eq!(
+            skipped.to_string(),
+            "2 members with unsafe paths and 2 links"
+        );
+        assert!(target.join("ok").is_file());
+        assert!(target.join("readonly/file").is_file());
+        let readonly = target.join("readonly");
+        let mode = fs::metadata(&readonly).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o555);
+        assert!(!dir.join("escaped").exists());
+        assert!(!target.join("link").exists());
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 2);
+        // Otherwise the directory can't be removed by users other than root.
+        fs::set_permissions(&readonly, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
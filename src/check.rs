@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use toml_edit::{ImDocument, Item, Table, TableLike};
+
+use crate::config::{self, Config, Style, HOOKS, STATUS_SEGMENTS};
+use crate::keymap::Key;
+use crate::{commands, highlight};
+
+/// A problem found in a configuration file at a 1-based line and column.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Checks a configuration file and returns all problems found in it.
+/// Key bindings can use the given actions besides the ones defined in the file itself.
+/// Only failing to read the file is an error, invalid contents are reported as diagnostics.
+pub fn check_config(path: &Path, actions: &[String]) -> Result<Vec<Diagnostic>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let mut diagnostics = check(&contents, actions);
+    // Anything not located by the checks still prevents the configuration from loading.
+    if let Err(error) = config::read_config(path) {
+        if diagnostics.is_empty() {
+            diagnostics.push(Diagnostic {
+                line: 1,
+                column: 1,
+                message: format!("{:#}", error),
+            });
+        }
+    }
+    Ok(diagnostics)
+}
+
+fn check(contents: &str, actions: &[String]) -> Vec<Diagnostic> {
+    let mut checker = Checker {
+        contents,
+        actions: actions.to_vec(),
+        diagnostics: vec![],
+    };
+    match ImDocument::parse(contents) {
+        Ok(document) => {
+            if let Err(error) = toml::from_str::<Config>(contents) {
+                checker.report(error.span(), error.message().to_owned());
+            }
+            checker.check_document(document.as_table());
+        }
+        Err(error) => checker.report(error.span(), error.message().to_owned()),
+    }
+    checker
+        .diagnostics
+        .sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    checker.diagnostics
+}
+
+struct Checker<'c> {
+    contents: &'c str,
+    /// Names of the actions which can be bound to keys.
+    actions: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn check_document(&mut self, document: &Table) {
+        if let Some(actions) = document.get("actions").and_then(Item::as_table_like) {
+            self.check_actions(actions);
+        }
+        if let Some(keys) = document.get("keys").and_then(Item::as_table_like) {
+            self.check_keys(keys, "keys");
+        }
+        if let Some(styles) = document.get("style").and_then(Item::as_table_like) {
+            self.check_styles(styles);
+        }
+        if let Some(status) = document.get("status").and_then(Item::as_table_like) {
+            for (_, item) in status.iter() {
+                for value in item.as_array().into_iter().flatten() {
+                    let Some(segment) = value.as_str() else {
+                        continue;
+                    };
+                    if !STATUS_SEGMENTS.contains(&segment) {
+                        self.report(value.span(), format!("unknown status segment: {}", segment));
+                    }
+                }
+            }
+        }
+        if let Some(hooks) = document.get("hooks").and_then(Item::as_table_like) {
+            for (name, _) in hooks.iter() {
+                if !HOOKS.contains(&name) {
+                    let span = hooks.key(name).and_then(|key| key.span());
+                    self.report(span, format!("unknown hook: {}", name));
+                }
+            }
+        }
+        if let Some(files) = document.get("files") {
+            self.check_files(files);
+        }
+        if let Some(openers) = document.get("openers") {
+            self.check_openers(openers);
+        }
+        if let Some(theme) = document.get("preview_theme").and_then(Item::as_value) {
+            if let Some(name) = theme.as_str() {
+                if !highlight::theme_names().contains(&name) {
+                    self.report(theme.span(), format!("unknown preview theme: {}", name));
+                }
+            }
+        }
+    }
+
+    /// Checks the key names and actions of key bindings including sequences like `g`.
+    fn check_keys(&mut self, keys: &dyn TableLike, prefix: &str) {
+        for (name, item) in keys.iter() {
+            if let Err(error) = Key::parse(name) {
+                let span = keys.key(name).and_then(|key| key.span());
+                self.report(span, error.to_string());
+            }
+            let key = format!("{}.{}", prefix, name);
+            if let Some(action) = item.as_str() {
+                if action.is_empty() {
+                    continue;
+                }
+                if let Err(error) = commands::check_action(action, &self.actions) {
+                    self.report(
+                        item.span(),
+                        format!("invalid action for {}: {}", key, error),
+                    );
+                }
+            } else if let Some(table) = item.as_table_like() {
+                self.check_keys(table, &key);
+            } else {
+                self.report(item.span(), format!("{} is not an action", key));
+            }
+        }
+    }
+
+    /// Checks that actions have a command and aren't hidden by a command of the same name.
+    fn check_actions(&mut self, actions: &dyn TableLike) {
+        for (name, item) in actions.iter() {
+            self.actions.push(name.to_owned());
+            let span = actions.key(name).and_then(|key| key.span());
+            if commands::find(name).is_some() {
+                self.report(
+                    span.clone(),
+                    format!("action {} has the name of a command", name),
+                );
+            }
+            let command = item
+                .as_table_like()
+                .and_then(|action| action.get("command"))
+                .and_then(Item::as_str);
+            if command.is_none_or(|command| command.trim().is_empty()) {
+                self.report(span, format!("action {} has no command", name));
+            }
+        }
+    }
+
+    fn check_styles(&mut self, styles: &dyn TableLike) {
+        for (name, style) in styles.iter() {
+            let Some(style) = style.as_table_like() else {
+                continue;
+            };
+            for field in ["fg", "bg"] {
+                let Some(value) = style.get(field) else {
+                    continue;
+                };
+                if let Some(color) = value.as_str() {
+                    if config::parse_color(color).is_none() {
+                        let message =
+                            format!("invalid colour for style.{}.{}: {}", name, field, color);
+                        self.report(value.span(), message);
+                    }
+                }
+            }
+            let modifiers = style.get("modifiers").and_then(Item::as_array);
+            for value in modifiers.into_iter().flatten() {
+                let Some(modifier) = value.as_str() else {
+                    continue;
+                };
+                if config::parse_modifier(modifier).is_none() {
+                    let message = format!("unknown modifier for style.{}: {}", name, modifier);
+                    self.report(value.span(), message);
+                }
+            }
+        }
+    }
+
+    /// Checks that file rules use file styles and that no extension is listed twice.
+    fn check_files(&mut self, files: &Item) {
+        let styles = Style::default();
+        let mut extensions: HashMap<String, usize> = HashMap::new();
+        for rule in tables(files) {
+            if let Some(value) = rule.get("style") {
+                if let Some(style) = value.as_str() {
+                    if styles.files_style(style).is_none() {
+                        self.report(value.span(), format!("unknown file style: {}", style));
+                    }
+                }
+            }
+            let values = rule.get("extensions").and_then(Item::as_array);
+            for value in values.into_iter().flatten() {
+                let Some(extension) = value.as_str() else {
+                    continue;
+                };
+                let line = self.position(value.span()).0;
+                match extensions.get(extension) {
+                    Some(first) => {
+                        let message = format!(
+                            "duplicate extension {} (first listed on line {})",
+                            extension, first
+                        );
+                        self.report(value.span(), message);
+                    }
+                    None => {
+                        extensions.insert(extension.to_owned(), line);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_openers(&mut self, openers: &Item) {
+        for opener in tables(openers) {
+            let command = opener.get("command").and_then(Item::as_str);
+            if command.is_none_or(|command| command.trim().is_empty()) {
+                let message = match opener.get("name").and_then(Item::as_str) {
+                    Some(name) => format!("opener {} has no command", name),
+                    None => "opener has no command".to_owned(),
+                };
+                // Report at the first key as tables don't keep their position.
+                let span = opener
+                    .iter()
+                    .next()
+                    .and_then(|(key, _)| opener.key(key))
+                    .and_then(|key| key.span());
+                self.report(span, message);
+            }
+            let mimes = opener.get("mime").and_then(Item::as_array);
+            for value in mimes.into_iter().flatten() {
+                let Some(mime) = value.as_str() else {
+                    continue;
+                };
+                if mime != "*" && !mime.contains('/') {
+                    self.report(value.span(), format!("invalid mime type: {}", mime));
+                }
+            }
+        }
+    }
+
+    fn report(&mut self, span: Option<Range<usize>>, message: String) {
+        let (line, column) = self.position(span);
+        self.diagnostics.push(Diagnostic {
+            line,
+            column,
+            message,
+        });
+    }
+
+    /// Converts the start of a byte span into a line and column.
+    fn position(&self, span: Option<Range<usize>>) -> (usize, usize) {
+        let offset = span.map_or(0, |span| span.start).min(self.contents.len());
+        let before = &self.contents[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit('\n')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .count()
+            + 1;
+        (line, column)
+    }
+}
+
+/// Returns the tables of an array of tables or an inline array of tables.
+fn tables(item: &Item) -> Vec<&dyn TableLike> {
+    match item {
+        Item::ArrayOfTables(tables) => tables.iter().map(|table| table as &dyn TableLike).collect(),
+        _ => item
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|value| value.as_inline_table())
+            .map(|table| table as &dyn TableLike)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_default_config() {
+        assert_eq!(check(config::DEFAULT_CONFIG, &[]), vec![]);
+    }
+
+    #[test]
+    fn test_check() {
+        let contents = r#"
+[keys]
+q = "quit"
+x = "bogus"
+hyper-x = "top"
+g = { g = "goto_top", e = ":cd", d = "deploy" }
+
+[status]
+left = ["git_branch", "weather"]
+
+[style]
+directory = { fg = "bleu", modifiers = ["bold", "wobbly"] }
+
+[[files]]
+extensions = ["mp4", "mkv"]
+style = "movie"
+
+[[files]]
+extensions = ["mp4"]
+style = "video"
+
+[actions]
+deploy = { command = "make deploy", confirm = true }
+copy = { command = "" }
+
+[hooks]
+on_cd = "echo $fm_root > /tmp/cwd"
+on_rename = "true"
+
+[[openers]]
+name = "viewer"
+mime = ["image"]
+"#;
+        let messages: Vec<String> = check(contents, &[])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "4:5: invalid action for keys.x: unknown command: bogus",
+                "5:1: unknown key: hyper-x",
+                "6:27: invalid action for keys.g.e: usage: cd <path>",
+                "9:23: unknown status segment: weather",
+                "12:20: invalid colour for style.directory.fg: bleu",
+                "12:49: unknown modifier for style.directory: wobbly",
+                "16:9: unknown file style: movie",
+                "19:15: duplicate extension mp4 (first listed on line 15)",
+                "24:1: action copy has the name of a command",
+                "24:1: action copy has no command",
+                "28:1: unknown hook: on_rename",
+                "31:1: opener viewer has no command",
+                "32:9: invalid mime type: image",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_preview_theme() {
+        let messages: Vec<String> = check("preview_theme = \"neon\"\n", &[])
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(messages, vec!["1:17: unknown preview theme: neon"]);
+    }
+
+    #[test]
+    fn test_check_syntax_error() {
+        let diagnostics = check("show_hidden = true\nicon_spacing = \n", &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 2);
+    }
+}
@@ -0,0 +1,512 @@
+use std::path::Path;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::error;
+use unicode_width::UnicodeWidthStr;
+
+use crate::commands;
+use crate::completion::{self, Completion, CompletionKind};
+use crate::history::{History, PromptKind};
+
+/// Message shown in the status bar until the next key press.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Info(String),
+    Error(String),
+}
+
+pub struct CommandBar {
+    pub command_entry_mode: bool,
+    pub prompt_text: String,
+    pub input_text: String,
+    /// Byte offset of the cursor in the input text (always on a char boundary).
+    pub cursor: usize,
+    pub history: History,
+    pub completion: Option<Completion>,
+    pub message: Option<Message>,
+    history_position: Option<HistoryPosition>,
+    search: Option<HistorySearch>,
+}
+
+/// Position while browsing the history with up and down keys.
+struct HistoryPosition {
+    kind: PromptKind,
+    /// Index counted from the newest entry.
+    index: usize,
+    /// Input typed before browsing the history.
+    draft: String,
+}
+
+/// State of an incremental reverse history search.
+struct HistorySearch {
+    kind: Option<PromptKind>,
+    query: String,
+    /// Index of the current match counted from the newest entry.
+    index: Option<usize>,
+    /// Input typed before starting the search.
+    original: String,
+}
+
+impl Default for CommandBar {
+    fn default() -> Self {
+        CommandBar {
+            command_entry_mode: false,
+            prompt_text: ":".into(),
+            input_text: String::default(),
+            cursor: 0,
+            history: History::default(),
+            completion: None,
+            message: None,
+            history_position: None,
+            search: None,
+        }
+    }
+}
+
+impl CommandBar {
+    /// Enters command entry mode with the given text already typed in.
+    pub fn open(&mut self, text: &str) {
+        self.input_text = text.into();
+        self.cursor = self.input_text.len();
+        self.command_entry_mode = true;
+        self.completion = None;
+        self.history_position = None;
+    }
+
+    /// Leaves command entry mode and discards the input.
+    pub fn close(&mut self) {
+        self.input_text = String::default();
+        self.cursor = 0;
+        self.prompt_text = ":".into();
+        self.command_entry_mode = false;
+        self.completion = None;
+        self.history_position = None;
+        self.search = None;
+    }
+
+    /// Completes the word before the cursor.
+    /// A single candidate is inserted right away, otherwise the common prefix is inserted
+    /// and further calls cycle through the candidates.
+    pub fn complete(&mut self, base: &Path, keys: &[&str]) {
+        if let Some(completion) = &mut self.completion {
+            if completion.candidates.len() > 1 {
+                let count = completion.candidates.len();
+                let selected = completion.selected.map_or(0, |index| (index + 1) % count);
+                self.select_candidate(selected);
+                return;
+            }
+        }
+        let commands = commands::names();
+        let completion = Completion::new(&self.input_text[..self.cursor], base, &commands, keys);
+        match completion.candidates.len() {
+            0 => {}
+            1 => {
+                let mut candidate = completion.candidates[0].clone();
+                match completion.kind {
+                    CompletionKind::Key => candidate.push('='),
+                    // Quoted directories end in a quote, so check the unquoted path.
+                    CompletionKind::Path if completion::unquote(&candidate).ends_with('/') => {}
+                    _ => candidate.push(' '),
+                }
+                self.replace_word(completion.start, &candidate);
+            }
+            _ => {
+                // Never replace the typed word with a shorter prefix.
+                let prefix = completion.common_prefix();
+                let word = &self.input_text[completion.start..self.cursor];
+                if completion::unquote(&prefix).len() >= completion::unquote(word).len() {
+                    self.replace_word(completion.start, &prefix);
+                }
+                self.completion = Some(completion);
+            }
+        }
+    }
+
+    /// Cycles backwards through the completion candidates.
+    pub fn complete_previous(&mut self) {
+        if let Some(completion) = &self.completion {
+            let count = completion.candidates.len();
+            let selected = completion
+                .selected
+                .map_or(count - 1, |index| (index + count - 1) % count);
+            self.select_candidate(selected);
+        }
+    }
+
+    fn select_candidate(&mut self, index: usize) {
+        if let Some(mut completion) = self.completion.take() {
+            completion.selected = Some(index);
+            let candidate = completion.candidates[index].clone();
+            self.replace_word(completion.start, &candidate);
+            self.completion = Some(completion);
+        }
+    }
+
+    fn replace_word(&mut self, start: usize, text: &str) {
+        self.input_text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Adds the current input to the history.
+    pub fn record(&mut self) {
+        let input = self.input_text.trim();
+        // Skip empty input and prompts which were opened but never filled in.
+        let unfilled = input.split_whitespace().count() == 1 && self.input_text.ends_with(' ');
+        if input.is_empty() || unfilled {
+            return;
+        }
+        if let Err(error) = self.history.add(input) {
+            error!("could not save command history: {}", error);
+        }
+    }
+
+    /// Returns the prompt shown in front of the input.
+    pub fn prompt(&self) -> String {
+        match &self.search {
+            Some(search) if search.index.is_none() && !search.query.is_empty() => {
+                format!("(failed reverse-i-search)`{}': ", search.query)
+            }
+            Some(search) => format!("(reverse-i-search)`{}': ", search.query),
+            None => self.prompt_text.clone(),
+        }
+    }
+
+    /// Returns the terminal column of the cursor relative to the start of the bar.
+    pub fn cursor_column(&self) -> usize {
+        self.prompt().width() + self.input_text[..self.cursor].width()
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Starts a reverse history search or moves to the next older match.
+    pub fn search_history(&mut self) {
+        if let Some(search) = &mut self.search {
+            let start = search.index.map_or(0, |index| index + 1);
+            let query = search.query.clone();
+            self.search_from(start, &query);
+        } else {
+            let kind = match PromptKind::of(&self.input_text) {
+                PromptKind::Command => None,
+                kind => Some(kind),
+            };
+            self.history_position = None;
+            self.search = Some(HistorySearch {
+                kind,
+                query: String::default(),
+                index: None,
+                original: self.input_text.clone(),
+            });
+        }
+    }
+
+    /// Handles a key while searching the history.
+    /// Enter accepts the match for editing and Esc restores the original input.
+    pub fn search_key(&mut self, key: KeyEvent) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        match (key.code, key.modifiers) {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.search_history(),
+            (KeyCode::Esc, _) | (KeyCode::Char('g'), KeyModifiers::CONTROL) => {
+                self.input_text = search.original.clone();
+                self.cursor = self.input_text.len();
+                self.search = None;
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                let mut query = search.query.clone();
+                query.push(c);
+                let start = search.index.unwrap_or(0);
+                self.search_from(start, &query);
+            }
+            (KeyCode::Backspace, _) => {
+                let mut query = search.query.clone();
+                query.pop();
+                self.search_from(0, &query);
+            }
+            (KeyCode::Enter, _) => self.search = None,
+            _ => {
+                self.search = None;
+                self.edit(key);
+            }
+        }
+    }
+
+    fn search_from(&mut self, start: usize, query: &str) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+        let entries = self.history.entries(search.kind);
+        let found = entries
+            .iter()
+            .rev()
+            .enumerate()
+            .skip(start)
+            .find(|(_, entry)| entry.contains(query));
+        search.query = query.to_owned();
+        match found {
+            Some((index, entry)) => {
+                search.index = Some(index);
+                self.input_text = entry.to_string();
+            }
+            None if query.is_empty() => {
+                search.index = None;
+                self.input_text = search.original.clone();
+            }
+            // Keep showing the last match when the query stops matching.
+            None => search.index = None,
+        }
+        self.cursor = self.input_text.len();
+    }
+
+    /// Replaces the input with an older history entry of the same prompt kind.
+    pub fn history_previous(&mut self) {
+        let position = self.history_position.take().unwrap_or(HistoryPosition {
+            kind: PromptKind::of(&self.input_text),
+            index: usize::MAX,
+            draft: self.input_text.clone(),
+        });
+        let entries = self.history.entries(Some(position.kind));
+        let index = position
+            .index
+            .wrapping_add(1)
+            .min(entries.len().saturating_sub(1));
+        if let Some(entry) = entries.iter().rev().nth(index) {
+            self.input_text = entry.to_string();
+            self.cursor = self.input_text.len();
+            self.history_position = Some(HistoryPosition { index, ..position });
+        } else {
+            self.history_position = None;
+        }
+    }
+
+    /// Replaces the input with a newer history entry, or the draft input after the newest.
+    pub fn history_next(&mut self) {
+        let Some(position) = self.history_position.take() else {
+            return;
+        };
+        if position.index == 0 {
+            self.input_text = position.draft;
+        } else {
+            let index = position.index - 1;
+            let entries = self.history.entries(Some(position.kind));
+            self.input_text = entries
+                .iter()
+                .rev()
+                .nth(index)
+                .map(|entry| entry.to_string())
+                .unwrap_or_default();
+            self.history_position = Some(HistoryPosition { index, ..position });
+        }
+        self.cursor = self.input_text.len();
+    }
+
+    /// Applies a line editing key to the input, returns false if the key is not an editing key.
+    pub fn edit(&mut self, key: KeyEvent) -> bool {
+        self.completion = None;
+        match (key.code, key.modifiers) {
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => self.insert(c),
+            (KeyCode::Backspace, _) => self.backspace(),
+            (KeyCode::Delete, _) | (KeyCode::Char('d'), KeyModifiers::CONTROL) => self.delete(),
+            (KeyCode::Left, KeyModifiers::NONE) | (KeyCode::Char('b'), KeyModifiers::CONTROL) => {
+                self.left()
+            }
+            (KeyCode::Right, KeyModifiers::NONE) | (KeyCode::Char('f'), KeyModifiers::CONTROL) => {
+                self.right()
+            }
+            (KeyCode::Home, _) | (KeyCode::Char('a'), KeyModifiers::CONTROL) => self.cursor = 0,
+            (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                self.cursor = self.input_text.len()
+            }
+            (KeyCode::Left, KeyModifiers::CONTROL) | (KeyCode::Char('b'), KeyModifiers::ALT) => {
+                self.word_left()
+            }
+            (KeyCode::Right, KeyModifiers::CONTROL) | (KeyCode::Char('f'), KeyModifiers::ALT) => {
+                self.word_right()
+            }
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.delete_word_backward(),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                self.input_text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            (KeyCode::Char('k'), KeyModifiers::CONTROL) => self.input_text.truncate(self.cursor),
+            (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                self.history_previous()
+            }
+            (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => self.history_next(),
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.search_history(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.input_text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Inserts pasted text at the cursor, line breaks are replaced with spaces.
+    pub fn paste(&mut self, text: &str) {
+        let text: String = text
+            .trim_end_matches(['\r', '\n'])
+            .chars()
+            .map(|c| if c == '\n' || c == '\r' { ' ' } else { c })
+            .filter(|c| !c.is_control())
+            .collect();
+        self.input_text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(c) = self.input_text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.input_text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.input_text.len() {
+            self.input_text.remove(self.cursor);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if let Some(c) = self.input_text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn right(&mut self) {
+        if let Some(c) = self.input_text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    /// Moves the cursor to the start of the current or previous word.
+    pub fn word_left(&mut self) {
+        let before = &self.input_text[..self.cursor];
+        let end = before.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let start = end.trim_end_matches(char::is_alphanumeric);
+        self.cursor = start.len();
+    }
+
+    /// Moves the cursor to the end of the current or next word.
+    pub fn word_right(&mut self) {
+        let after = &self.input_text[self.cursor..];
+        let start = after.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let end = start.trim_start_matches(char::is_alphanumeric);
+        self.cursor = self.input_text.len() - end.len();
+    }
+
+    /// Deletes the whitespace delimited word before the cursor.
+    pub fn delete_word_backward(&mut self) {
+        let before = &self.input_text[..self.cursor];
+        let start = before
+            .trim_end_matches(char::is_whitespace)
+            .trim_end_matches(|c: char| !c.is_whitespace())
+            .len();
+        self.input_text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_edit_motions() {
+        let mut bar = CommandBar::default();
+        bar.open("sh make test");
+        bar.edit(key(KeyCode::Char('b'), KeyModifiers::ALT));
+        assert_eq!(bar.cursor, 8);
+        bar.edit(key(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(bar.input_text, "sh test");
+        bar.edit(key(KeyCode::Home, KeyModifiers::NONE));
+        bar.edit(key(KeyCode::Char('f'), KeyModifiers::ALT));
+        assert_eq!(bar.cursor, 2);
+        bar.edit(key(KeyCode::Delete, KeyModifiers::NONE));
+        bar.edit(key(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert_eq!(bar.input_text, "test");
+        assert_eq!(bar.cursor, 0);
+    }
+
+    #[test]
+    fn test_history_navigation() {
+        let mut bar = CommandBar::default();
+        bar.history.add("sh make").unwrap();
+        bar.history.add("search foo").unwrap();
+        bar.history.add("sh make test").unwrap();
+        bar.open("sh ");
+        bar.history_previous();
+        assert_eq!(bar.input_text, "sh make test");
+        bar.history_previous();
+        bar.history_previous();
+        assert_eq!(bar.input_text, "sh make");
+        bar.history_next();
+        bar.history_next();
+        assert_eq!(bar.input_text, "sh ");
+
+        bar.search_history();
+        bar.search_key(key(KeyCode::Char('m'), KeyModifiers::NONE));
+        assert_eq!(bar.input_text, "sh make test");
+        bar.search_key(key(KeyCode::Char('r'), KeyModifiers::CONTROL));
+        assert_eq!(bar.input_text, "sh make");
+        bar.search_key(key(KeyCode::Char('x'), KeyModifiers::NONE));
+        assert_eq!(bar.prompt(), "(failed reverse-i-search)`mx': ");
+        bar.search_key(key(KeyCode::Esc, KeyModifiers::NONE));
+        assert_eq!(bar.input_text, "sh ");
+        assert!(!bar.is_searching());
+    }
+
+    #[test]
+    fn test_complete() {
+        let mut bar = CommandBar::default();
+        bar.open("ne");
+        bar.complete(Path::new("/"), &[]);
+        assert_eq!(bar.input_text, "new-");
+        bar.complete(Path::new("/"), &[]);
+        assert_eq!(bar.input_text, "new-dir");
+        bar.complete(Path::new("/"), &[]);
+        assert_eq!(bar.input_text, "new-file");
+        bar.complete_previous();
+        assert_eq!(bar.input_text, "new-dir");
+        bar.open("set show");
+        bar.complete(Path::new("/"), &["show_hidden"]);
+        assert_eq!(bar.input_text, "set show_hidden=");
+
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path();
+        std::fs::create_dir_all(base.join("my dir/sub")).unwrap();
+        std::fs::write(base.join("a b"), "").unwrap();
+        std::fs::write(base.join("abc"), "").unwrap();
+        bar.open("cd my");
+        bar.complete(base, &[]);
+        assert_eq!(bar.input_text, "cd 'my dir/'");
+        bar.complete(base, &[]);
+        assert_eq!(bar.input_text, "cd 'my dir/sub/'");
+        bar.open("sh cat a");
+        bar.complete(base, &[]);
+        assert_eq!(bar.input_text, "sh cat a");
+        bar.complete(base, &[]);
+        assert_eq!(bar.input_text, "sh cat 'a b'");
+    }
+
+    #[test]
+    fn test_edit_unicode() {
+        let mut bar = CommandBar::default();
+        bar.open("new-file 日本");
+        assert_eq!(bar.cursor_column(), 1 + 9 + 4);
+        bar.edit(key(KeyCode::Left, KeyModifiers::NONE));
+        bar.edit(key(KeyCode::Char('ö'), KeyModifiers::NONE));
+        assert_eq!(bar.input_text, "new-file 日ö本");
+        assert_eq!(bar.cursor_column(), 1 + 9 + 3);
+        bar.paste("a\nb\n");
+        assert_eq!(bar.input_text, "new-file 日öa b本");
+    }
+}
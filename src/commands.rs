@@ -0,0 +1,895 @@
+use std::fs;
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::application::Application;
+use crate::command_bar::Message;
+use crate::config::ShellMode;
+
+/// Arguments accepted by a command, the string is the placeholder shown in the help text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arguments {
+    None,
+    Required(&'static str),
+    Optional(&'static str),
+    /// One or more arguments.
+    Variadic(&'static str),
+    /// The unparsed rest of the line including any `;` separators.
+    Raw(&'static str),
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub arguments: Arguments,
+    pub help: &'static str,
+    pub run: fn(&mut Application<'_>, Vec<String>) -> Result<(), Error>,
+}
+
+impl CommandSpec {
+    pub fn usage(&self) -> String {
+        match self.arguments {
+            Arguments::None => self.name.to_owned(),
+            Arguments::Required(name) | Arguments::Raw(name) => format!("{} <{}>", self.name, name),
+            Arguments::Optional(name) => format!("{} [{}]", self.name, name),
+            Arguments::Variadic(name) => format!("{} <{}...>", self.name, name),
+        }
+    }
+
+    fn check(&self, arguments: &[String]) -> Result<(), Error> {
+        let valid = match self.arguments {
+            Arguments::None => arguments.is_empty(),
+            Arguments::Required(_) | Arguments::Raw(_) => arguments.len() == 1,
+            Arguments::Optional(_) => arguments.len() <= 1,
+            Arguments::Variadic(_) => !arguments.is_empty(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(anyhow!("usage: {}", self.usage()))
+        }
+    }
+}
+
+/// Every action of the file manager which can be run from the command bar.
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "action",
+        aliases: &[],
+        arguments: Arguments::Required("name"),
+        help: "Run an action from the [actions] table of the configuration",
+        run: |app, arguments| app.action(&arguments[0]),
+    },
+    CommandSpec {
+        name: "bottom",
+        aliases: &["goto-bottom"],
+        arguments: Arguments::None,
+        help: "Jump to the last file",
+        run: |app, _| {
+            app.bottom();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "cd",
+        aliases: &[],
+        arguments: Arguments::Required("path"),
+        help: "Change the root directory",
+        run: |app, arguments| {
+            let path = app.files.path.join(&arguments[0]);
+            // Remote paths are resolved by the host.
+            let path = match app.vfs.url(&path) {
+                Some(_) => path,
+                None => fs::canonicalize(path)?,
+            };
+            if !app.vfs.stat(&path).is_ok_and(|stat| stat.is_dir) {
+                return Err(anyhow!("not a directory: {}", path.display()));
+            }
+            app.jump_root(path)
+        },
+    },
+    CommandSpec {
+        name: "choose",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Choose the selected or marked files and quit in file chooser mode",
+        run: |app, _| app.choose(),
+    },
+    CommandSpec {
+        name: "clear",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Dismiss the output pane",
+        run: |app, _| {
+            app.clear();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "clear-files",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Clear selected, copied and cut files",
+        run: |app, _| {
+            app.clear_files();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "collapse",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Collapse the selected directory",
+        run: |app, _| {
+            app.collapse();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "command-bar",
+        aliases: &[],
+        arguments: Arguments::Optional("text"),
+        help: "Open the command bar with optional initial text",
+        run: |app, arguments| {
+            let text = arguments.first().map_or("", String::as_str);
+            app.command_bar.open(text);
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "compress",
+        aliases: &[],
+        arguments: Arguments::Required("name"),
+        help: "Compress the marked files into a .tar.gz, .tar.zst or .zip archive",
+        run: |app, arguments| app.compress(&arguments[0]),
+    },
+    CommandSpec {
+        name: "copy",
+        aliases: &["yank"],
+        arguments: Arguments::None,
+        help: "Copy the selected or marked files",
+        run: |app, _| {
+            app.copy();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "cp",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Run fm-cmd-cp in the selected directory",
+        run: |app, _| {
+            app.cmd_cp();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "cut",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Cut the selected or marked files",
+        run: |app, _| app.cut(),
+    },
+    CommandSpec {
+        name: "down",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Move the selection down",
+        run: |app, _| {
+            app.down();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "drag-and-drop",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Drag and drop the selected or marked files",
+        run: |app, _| app.drag_and_drop(),
+    },
+    CommandSpec {
+        name: "edit",
+        aliases: &["e"],
+        arguments: Arguments::None,
+        help: "Edit the marked or selected files with $VISUAL or $EDITOR in the same window",
+        run: |app, _| app.edit(),
+    },
+    CommandSpec {
+        name: "edit-window",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Edit the marked or selected files in an external window",
+        run: |app, _| app.editx(),
+    },
+    CommandSpec {
+        name: "edit-window-no",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Edit the marked or selected files in an external window without switching to it",
+        run: |app, _| app.editnox(),
+    },
+    CommandSpec {
+        name: "expand",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Expand the selected directory",
+        run: |app, _| {
+            app.expand();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "extract",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Extract the selected archive into a directory next to it",
+        run: |app, _| app.extract(),
+    },
+    CommandSpec {
+        name: "file-manager",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open a file manager in the selected directory",
+        run: |app, _| app.file_manager(),
+    },
+    CommandSpec {
+        name: "git-log",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Show the git log",
+        run: |app, _| {
+            app.git_log();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &[],
+        arguments: Arguments::Optional("command"),
+        help: "Show the usage of a command or list all commands",
+        run: |app, arguments| {
+            let text = if let Some(name) = arguments.first() {
+                let spec = find(name).ok_or_else(|| anyhow!("unknown command: {}", name))?;
+                let mut text = format!("{}: {}", spec.usage(), spec.help);
+                if !spec.aliases.is_empty() {
+                    text.push_str(&format!(" (aliases: {})", spec.aliases.join(", ")));
+                }
+                text
+            } else {
+                names().join(" ")
+            };
+            app.command_bar.message = Some(Message::Info(text));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "images",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open an image viewer in the root directory",
+        run: |app, _| {
+            app.images();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "mv",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Run fm-cmd-mv in the selected directory",
+        run: |app, _| {
+            app.cmd_mv();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "new-dir",
+        aliases: &["mkdir"],
+        arguments: Arguments::Variadic("names"),
+        help: "Create new directories",
+        run: |app, arguments| app.new_dir(arguments),
+    },
+    CommandSpec {
+        name: "new-file",
+        aliases: &["touch"],
+        arguments: Arguments::Variadic("names"),
+        help: "Create new files",
+        run: |app, arguments| app.new_file(arguments),
+    },
+    CommandSpec {
+        name: "open",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open the selected file with its first opener",
+        run: |app, _| app.open(),
+    },
+    CommandSpec {
+        name: "open-with",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Choose an opener for the selected file",
+        run: |app, _| app.open_with(),
+    },
+    CommandSpec {
+        name: "output-down",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Scroll the output pane down",
+        run: |app, _| {
+            app.output_down();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "output-up",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Scroll the output pane up",
+        run: |app, _| {
+            app.output_up();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "paste",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Paste copied or cut files into the selected directory",
+        run: |app, _| app.paste(false),
+    },
+    CommandSpec {
+        name: "paste-root",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Paste copied or cut files into the root directory",
+        run: |app, _| app.paste(true),
+    },
+    CommandSpec {
+        name: "path",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Run fm-cmd-path with the selected file",
+        run: |app, _| {
+            app.cmd_path();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "preview",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Preview file information",
+        run: |app, _| app.preview(),
+    },
+    CommandSpec {
+        name: "preview-down",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Scroll the preview pane down",
+        run: |app, _| {
+            app.preview_down();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "preview-up",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Scroll the preview pane up",
+        run: |app, _| {
+            app.preview_up();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        arguments: Arguments::None,
+        help: "Quit",
+        run: |app, _| app.quit(),
+    },
+    CommandSpec {
+        name: "quit-cd",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Quit and change the shell directory to the root directory",
+        run: |app, _| {
+            let last_dir_path = app.last_dir_path.clone();
+            app.quit_change(last_dir_path.as_ref())
+        },
+    },
+    CommandSpec {
+        name: "refresh",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Refresh the view",
+        run: |app, _| {
+            app.refresh();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "reload-config",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Reload the configuration files",
+        run: |app, _| {
+            app.reload_config()?;
+            app.command_bar.message = Some(Message::Info("configuration reloaded".into()));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "rename",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Rename the selected or marked files",
+        run: |app, _| app.rename(),
+    },
+    CommandSpec {
+        name: "root-down",
+        aliases: &["change-root"],
+        arguments: Arguments::None,
+        help: "Change the root to the selected directory",
+        run: |app, _| app.change_root(),
+    },
+    CommandSpec {
+        name: "root-up",
+        aliases: &["previous-root"],
+        arguments: Arguments::None,
+        help: "Change the root to the parent directory",
+        run: |app, _| app.previous_root(),
+    },
+    CommandSpec {
+        name: "search",
+        aliases: &["find"],
+        arguments: Arguments::Raw("text"),
+        help: "Select the first visible file containing the text",
+        run: |app, mut arguments| {
+            app.search(arguments.remove(0));
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "search-all",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Search files recursively in child directories",
+        run: |app, _| app.search_all(),
+    },
+    CommandSpec {
+        name: "select",
+        aliases: &["mark"],
+        arguments: Arguments::None,
+        help: "Mark or unmark the selected file",
+        run: |app, _| {
+            app.mark();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "session",
+        aliases: &[],
+        arguments: Arguments::Variadic("save|load name"),
+        help: "Save the layout of the tree under a name or restore a saved one",
+        run: |app, arguments| match arguments.as_slice() {
+            [action, name] if action == "save" => app.save_session(name),
+            [action, name] if action == "load" => app.load_session(name),
+            _ => Err(anyhow!("usage: session <save|load> <name>")),
+        },
+    },
+    CommandSpec {
+        name: "set",
+        aliases: &[],
+        arguments: Arguments::Variadic("key=value|key!|key?"),
+        help: "Change, toggle or show configuration values",
+        run: |app, arguments| {
+            let mut values = vec![];
+            for expression in &arguments {
+                values.extend(app.configuration.set(expression)?);
+            }
+            if values.is_empty() {
+                app.refresh();
+            } else {
+                app.command_bar.message = Some(Message::Info(values.join("  ")));
+            }
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "sh",
+        aliases: &[],
+        arguments: Arguments::Raw("command"),
+        help: "Run a shell command in the terminal",
+        run: |app, mut arguments| app.run_shell(arguments.remove(0), ShellMode::Interactive, true),
+    },
+    CommandSpec {
+        name: "sh-background",
+        aliases: &[],
+        arguments: Arguments::Raw("command"),
+        help: "Run a shell command in the background and report its exit status",
+        run: |app, mut arguments| app.run_shell(arguments.remove(0), ShellMode::Background, true),
+    },
+    CommandSpec {
+        name: "sh-capture",
+        aliases: &[],
+        arguments: Arguments::Raw("command"),
+        help: "Run a shell command and show its output below the file tree",
+        run: |app, mut arguments| app.run_shell(arguments.remove(0), ShellMode::Capture, true),
+    },
+    CommandSpec {
+        name: "shell",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open a shell in the same window",
+        run: |app, _| {
+            app.shell();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "shell-window",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open a shell in the selected directory in an external window",
+        run: |app, _| app.shellx(),
+    },
+    CommandSpec {
+        name: "shell-window-root",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open a shell in the root directory in an external window",
+        run: |app, _| app.shellx_root(),
+    },
+    CommandSpec {
+        name: "sort",
+        aliases: &[],
+        arguments: Arguments::Variadic("key [reverse]"),
+        help: "Sort files by name, natural, size, mtime, ctime, extension or git status",
+        run: |app, arguments| app.sort(&arguments[0], &arguments[1..]),
+    },
+    CommandSpec {
+        name: "toggle-expand",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Expand or collapse the selected directory",
+        run: |app, _| {
+            app.expand_toggle();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "toggle-hidden",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Show or hide hidden files",
+        run: |app, _| {
+            app.toggle_hidden();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "toggle-preview",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Show or hide the preview pane beside the tree",
+        run: |app, _| {
+            app.toggle_preview();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "top",
+        aliases: &["goto-top"],
+        arguments: Arguments::None,
+        help: "Jump to the first file",
+        run: |app, _| {
+            app.top();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "trash",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Move the selected or marked files to the trash",
+        run: |app, _| app.trash(),
+    },
+    CommandSpec {
+        name: "trust",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Allow the project configuration to run commands",
+        run: |app, _| app.trust(),
+    },
+    CommandSpec {
+        name: "up",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Move the selection up",
+        run: |app, _| {
+            app.up();
+            Ok(())
+        },
+    },
+    CommandSpec {
+        name: "vscode",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open the selected file in VS Code",
+        run: |app, _| app.vscode(),
+    },
+    CommandSpec {
+        name: "vscode-root",
+        aliases: &[],
+        arguments: Arguments::None,
+        help: "Open the root directory in VS Code",
+        run: |app, _| app.vscode_root(),
+    },
+];
+
+/// Returns the names of all commands.
+pub fn names() -> Vec<&'static str> {
+    COMMANDS.iter().map(|spec| spec.name).collect()
+}
+
+/// Looks up a command by name or alias, underscores match dashes so `quit_cd` finds `quit-cd`.
+pub fn find(name: &str) -> Option<&'static CommandSpec> {
+    let name = name.replace('_', "-");
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name == name || spec.aliases.contains(&name.as_str()))
+}
+
+/// Parses and runs a `;` separated list of commands.
+/// Nothing is run if any of the commands is unknown or has invalid arguments.
+pub fn execute(app: &mut Application, input: &str) -> Result<(), Error> {
+    for (spec, arguments) in parse(input)? {
+        (spec.run)(app, arguments)?;
+    }
+    Ok(())
+}
+
+/// Runs the action of a key binding. A command which needs arguments opens the command bar
+/// with its name so they can be typed in e.g. `new_file` prompts for the file names.
+/// Names which aren't commands run an action from the `[actions]` table.
+pub fn run_action(app: &mut Application, action: &str) -> Result<(), Error> {
+    if let Some(input) = action.strip_prefix(':') {
+        return execute(app, input);
+    }
+    let spec = match find(action) {
+        Some(spec) => spec,
+        None if app.configuration.actions.contains_key(action) => return app.action(action),
+        None => return Err(anyhow!("unknown command: {}", action)),
+    };
+    match spec.arguments {
+        Arguments::None | Arguments::Optional(_) => (spec.run)(app, vec![]),
+        _ => {
+            app.command_bar.open(&format!("{} ", spec.name));
+            Ok(())
+        }
+    }
+}
+
+/// Checks the action of a key binding which is either a command name, one of the given
+/// `[actions]` names or a `:` prefixed command line e.g. `":sh git-log"`.
+pub fn check_action(action: &str, actions: &[String]) -> Result<(), Error> {
+    match action.strip_prefix(':') {
+        Some(input) => {
+            if parse(input)?.is_empty() {
+                return Err(anyhow!("empty command"));
+            }
+            Ok(())
+        }
+        None if find(action).is_some() || actions.iter().any(|name| name == action) => Ok(()),
+        None => Err(anyhow!("unknown command: {}", action)),
+    }
+}
+
+/// Replaces `%` followed by a character with the value returned for it, `%%` is a literal `%`.
+/// Unknown placeholders are left as they are.
+pub fn expand_placeholders<F>(template: &str, value: F) -> String
+where
+    F: Fn(char) -> Option<String>,
+{
+    let mut expanded = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some(placeholder) => match value(placeholder) {
+                Some(value) => expanded.push_str(&value),
+                None => {
+                    expanded.push('%');
+                    expanded.push(placeholder);
+                }
+            },
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// Quotes text for a POSIX shell, single quotes inside are written as `'\''`.
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Splits input into `;` separated commands of whitespace separated words, and checks
+/// each command against its spec.
+/// Single quotes preserve text literally, double quotes and backslashes escape characters.
+pub fn parse(input: &str) -> Result<Vec<(&'static CommandSpec, Vec<String>)>, Error> {
+    let mut lexer = Lexer::new(input);
+    let mut commands = vec![];
+    loop {
+        lexer.skip_separators();
+        let Some(name) = lexer.word()? else {
+            break;
+        };
+        let spec = find(&name).ok_or_else(|| anyhow!("unknown command: {}", name))?;
+        let mut arguments = vec![];
+        if let Arguments::Raw(_) = spec.arguments {
+            let rest = lexer.rest().trim();
+            if !rest.is_empty() {
+                arguments.push(rest.to_owned());
+            }
+        } else {
+            while let Some(word) = lexer.word()? {
+                arguments.push(word);
+            }
+        }
+        spec.check(&arguments)?;
+        commands.push((spec, arguments));
+    }
+    Ok(commands)
+}
+
+/// Reads the words of command bar input one command at a time.
+struct Lexer<'i> {
+    input: &'i str,
+    position: usize,
+}
+
+impl<'i> Lexer<'i> {
+    fn new(input: &'i str) -> Self {
+        Lexer { input, position: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_separators(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace() || c == ';') {
+            self.bump();
+        }
+    }
+
+    fn rest(&mut self) -> &'i str {
+        let rest = &self.input[self.position..];
+        self.position = self.input.len();
+        rest
+    }
+
+    /// Returns the next word of the current command, or nothing at a `;` or the end of input.
+    fn word(&mut self) -> Result<Option<String>, Error> {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+        match self.peek() {
+            None | Some(';') => return Ok(None),
+            _ => {}
+        }
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == ';' {
+                break;
+            }
+            self.bump();
+            match c {
+                '\\' => word.extend(self.bump()),
+                '\'' => loop {
+                    match self.bump() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("unterminated single quote")),
+                    }
+                },
+                '"' => loop {
+                    match self.bump() {
+                        Some('"') => break,
+                        Some('\\') => match self.bump() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(anyhow!("unterminated double quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(anyhow!("unterminated double quote")),
+                    }
+                },
+                c => word.push(c),
+            }
+        }
+        Ok(Some(word))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let commands = parse(r#"new-file 'a b.rs' c\ d.rs "e \"f\".rs"; cd ..;;top"#).unwrap();
+        let commands: Vec<_> = commands
+            .iter()
+            .map(|(spec, arguments)| {
+                let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+                (spec.name, arguments)
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                ("new-file", vec!["a b.rs", "c d.rs", "e \"f\".rs"]),
+                ("cd", vec![".."]),
+                ("top", vec![]),
+            ]
+        );
+        assert!(parse("  ").unwrap().is_empty());
+        assert!(parse("cd 'foo").is_err());
+
+        let commands = parse("new_file a.rs b.rs; sh make; ls").unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0.name, "new-file");
+        assert_eq!(commands[0].1, vec!["a.rs", "b.rs"]);
+        assert_eq!(commands[1].1, vec!["make; ls"]);
+        assert_eq!(
+            parse("top; bogus").err().unwrap().to_string(),
+            "unknown command: bogus"
+        );
+        assert_eq!(parse("cd").err().unwrap().to_string(), "usage: cd <path>");
+    }
+
+    #[test]
+    fn test_check_action() {
+        let actions = vec!["deploy".to_owned()];
+        assert!(check_action("quit_cd", &actions).is_ok());
+        assert!(check_action("new_dir", &actions).is_ok());
+        assert!(check_action(":sh window-edit", &actions).is_ok());
+        assert!(check_action("deploy", &actions).is_ok());
+        assert!(check_action(":cd", &actions).is_err());
+        assert!(check_action(":", &actions).is_err());
+        assert!(check_action("bogus", &actions).is_err());
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        let value = |placeholder| match placeholder {
+            'f' => Some(shell_quote("it's.txt")),
+            'r' => Some(shell_quote("/tmp")),
+            _ => None,
+        };
+        assert_eq!(
+            expand_placeholders("cp %f %r/backup", value),
+            r"cp 'it'\''s.txt' '/tmp'/backup"
+        );
+        assert_eq!(
+            expand_placeholders("echo 100%% %x %", value),
+            "echo 100% %x %"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::path::Path;
+
+use crate::commands;
+use crate::config::SORT_KEYS;
+
+/// Candidates for the word under the cursor in the command bar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompletionKind {
+    #[default]
+    Command,
+    Key,
+    Path,
+}
+
+#[derive(Debug, Default)]
+pub struct Completion {
+    pub kind: CompletionKind,
+    pub candidates: Vec<String>,
+    /// Candidate currently inserted into the input when cycling through the menu.
+    pub selected: Option<usize>,
+    /// Byte offset of the start of the completed word.
+    pub start: usize,
+}
+
+impl Completion {
+    /// Completes the last word of the input which is either a command name,
+    /// a configuration key for `set`, a key for `sort` or a file path relative to the base directory.
+    pub fn new(input: &str, base: &Path, commands: &[&str], keys: &[&str]) -> Self {
+        // Only the last of several `;` separated commands is completed.
+        let command_start = command_start(input);
+        let start = word_start(input, command_start);
+        let word = &input[start..];
+        let command = input[command_start..]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default();
+        let (kind, mut candidates) = if input[command_start..start].trim().is_empty() {
+            (CompletionKind::Command, complete_words(word, commands))
+        } else if command == "set" {
+            (CompletionKind::Key, complete_words(word, keys))
+        } else if command == "sort" {
+            (CompletionKind::Key, complete_words(word, SORT_KEYS))
+        } else {
+            let paths = complete_path(&unquote(word), base);
+            let paths = paths.iter().map(|path| quote(path)).collect();
+            (CompletionKind::Path, paths)
+        };
+        candidates.sort();
+        candidates.dedup();
+        Completion {
+            kind,
+            candidates,
+            selected: None,
+            start,
+        }
+    }
+
+    /// Returns the longest prefix shared by all candidates.
+    /// Paths are compared unquoted, so quoted and plain candidates still share their prefix.
+    pub fn common_prefix(&self) -> String {
+        let candidates: Vec<String> = self.candidates.iter().map(|c| unquote(c)).collect();
+        let Some(first) = candidates.first() else {
+            return String::default();
+        };
+        let mut prefix = first.as_str();
+        for candidate in &candidates[1..] {
+            let length = prefix
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(candidate.len()), |((index, _), _)| index);
+            prefix = &prefix[..length];
+        }
+        match self.kind {
+            CompletionKind::Path => quote(prefix),
+            _ => prefix.to_owned(),
+        }
+    }
+}
+
+fn complete_words(word: &str, words: &[&str]) -> Vec<String> {
+    words
+        .iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(|candidate| candidate.to_string())
+        .collect()
+}
+
+/// Lists the entries of the directory part of the word which start with the file name part.
+/// Directories are suffixed with a slash so completion can continue into them.
+fn complete_path(word: &str, base: &Path) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(index) => (&word[..=index], &word[index + 1..]),
+        None => ("", word),
+    };
+    let search_dir = if let Some(rest) = dir.strip_prefix("~/") {
+        dirs::home_dir().unwrap_or_default().join(rest)
+    } else if dir.is_empty() {
+        base.to_owned()
+    } else {
+        base.join(dir)
+    };
+    let Ok(entries) = fs::read_dir(search_dir) else {
+        return vec![];
+    };
+    let mut candidates = vec![];
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Hidden files are only completed when explicitly asked for.
+        if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+            continue;
+        }
+        let is_dir = entry.path().is_dir();
+        candidates.push(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }));
+    }
+    candidates
+}
+
+/// Returns the start of the last command, after the last `;` which isn't quoted or escaped.
+fn command_start(input: &str) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, ';') => start = index + 1,
+            _ => {}
+        }
+    }
+    start
+}
+
+/// Returns the start of the last word after `from`. Whitespace which is quoted or escaped
+/// with a backslash doesn't separate words.
+fn word_start(input: &str, from: usize) -> usize {
+    let mut start = from;
+    let mut quote = None;
+    let mut chars = input[from..].char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, c) if c.is_whitespace() => start = from + index + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+/// Removes the quotes and backslashes of a word, which may still be missing its closing quote.
+pub fn unquote(word: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\\') => unquoted.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            _ => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// Quotes a completed path which contains characters the command bar or a shell would
+/// interpret, a leading `~/` stays unquoted so it is still expanded.
+fn quote(path: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "/._-+,:@=~".contains(c);
+    if path.chars().all(plain) {
+        return path.to_owned();
+    }
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", commands::shell_quote(rest)),
+        None => commands::shell_quote(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_words() {
+        let commands = ["new-dir", "new-file", "search", "set"];
+        let completion = Completion::new("new", Path::new("/"), &commands, &[]);
+        assert_eq!(completion.candidates, vec!["new-dir", "new-file"]);
+        assert_eq!(completion.common_prefix(), "new-");
+        let completion = Completion::new("set sh", Path::new("/"), &commands, &["show_hidden"]);
+        assert_eq!(completion.candidates, vec!["show_hidden"]);
+        assert_eq!(completion.start, 4);
+        let completion = Completion::new("top; se", Path::new("/"), &commands, &[]);
+        assert_eq!(completion.candidates, vec!["search", "set"]);
+        assert_eq!(completion.start, 5);
+        let completion = Completion::new("sort n", Path::new("/"), &commands, &[]);
+        assert_eq!(completion.candidates, vec!["name", "natural"]);
+    }
+
+    #[test]
+    fn test_complete_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = temp.path();
+        fs::create_dir_all(base.join("src/bin")).unwrap();
+        fs::write(base.join("src/config.rs"), "").unwrap();
+        fs::write(base.join("src/.hidden"), "").unwrap();
+        let completion = Completion::new("new-file sr", base, &[], &[]);
+        assert_eq!(completion.candidates, vec!["src/"]);
+        let completion = Completion::new("sh cat src/", base, &[], &[]);
+        assert_eq!(completion.candidates, vec!["src/bin/", "src/config.rs"]);
+        assert_eq!(completion.common_prefix(), "src/");
+
+        fs::create_dir(base.join("my dir")).unwrap();
+        fs::write(base.join("my dir/it's.txt"), "").unwrap();
+        fs::write(base.join("my dir/b.txt"), "").unwrap();
+        let completion = Completion::new("cd my", base, &[], &[]);
+        assert_eq!(completion.candidates, vec!["'my dir/'"]);
+        let completion = Completion::new("sh cat 'my dir/", base, &[], &[]);
+        assert_eq!(
+            completion.candidates,
+            vec![r"'my dir/b.txt'", r"'my dir/it'\''s.txt'"]
+        );
+        assert_eq!(completion.start, 7);
+        assert_eq!(completion.common_prefix(), "'my dir/'");
+        let completion = Completion::new(r"cd my\ dir/i", base, &[], &[]);
+        assert_eq!(completion.candidates, vec![r"'my dir/it'\''s.txt'"]);
+
+        // Quoted and plain candidates share their unquoted prefix.
+        fs::write(base.join("a b"), "").unwrap();
+        fs::write(base.join("abc"), "").unwrap();
+        let completion = Completion::new("sh cat a", base, &[], &[]);
+        assert_eq!(completion.candidates, vec!["'a b'", "abc"]);
+        assert_eq!(completion.common_prefix(), "a");
+
+        // A quoted `;` doesn't start a new command.
+        fs::create_dir(base.join("x;y")).unwrap();
+        let completion = Completion::new("cd 'x;", base, &[], &[]);
+        assert_eq!(completion.kind, CompletionKind::Path);
+        assert_eq!(completion.candidates, vec!["'x;y/'"]);
+    }
+}
@@ -1,13 +1,70 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use anyhow::{anyhow, Error, Result};
-use serde::Deserialize;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml::Value;
+use tui::style::{self, Color, Modifier};
 
 pub const DEFAULT_CONFIG: &str = include_str!("../desktop/config.toml");
 
-#[derive(Debug, Deserialize)]
+/// System wide configuration applied on top of the built-in defaults.
+pub const SYSTEM_CONFIG: &str = "/etc/fm/config.toml";
+
+/// Project configuration looked up in the root directory and its parents.
+pub const PROJECT_CONFIG: &str = ".fm.toml";
+
+/// Keys which run commands, a project configuration can only set them once it is trusted.
+pub const TRUSTED_KEYS: &[&str] = &["actions", "edit_tabs", "hooks", "keys", "openers", "shell"];
+
+/// Key table of the configuration which used to be copied to the user configuration on the
+/// first start, when the table was still ignored. It is removed from the user configuration
+/// so it can't override the current default bindings.
+const LEGACY_KEYS: &str = r#"[keys]
+"/" = "find"
+":" = "command_bar"
+";" = "expand"
+"?" = "search"
+C = "clear_files"
+"C-r" = "refresh"
+"C-s" = ":sh window-session-root"
+D = ":sh drag-n-drop"
+E = ":sh window-edit"
+I = ":sh imv-dir"
+L = ":sh git-log"
+N = "new_dir"
+Q = "quit_cd"
+S = ":sh window-session"
+T = "trash"
+V = ":sh vscode"
+a = "root_up"
+c = "cut"
+down = "down"
+e = "edit"
+escape = "clear"
+f = "root_down"
+g = { g = "goto_top", e = "goto_bottom" }
+i = "preview"
+j = "collapse"
+k = "down"
+l = "up"
+left = "collapse"
+n = "new_file"
+o = "open"
+p = "paste"
+q = "quit"
+r = "rename"
+right = "expand"
+s = "shell"
+space = "select"
+up = "up"
+y = "copy"
+"#;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub icon_spacing: u8,
     pub status_line_spacing: u8,
@@ -17,87 +74,207 @@ pub struct Config {
     pub indent_guide: String,
     pub indent_spaces: u8,
     pub mouse: bool,
+    /// Editor flags used when several files are edited at once, empty for none.
+    pub edit_tabs: String,
+    pub preview: bool,
+    pub preview_lines: usize,
+    pub preview_theme: String,
     pub show_hidden: bool,
+    pub sort: Sort,
     pub shell: Vec<String>,
     pub info: Vec<String>,
     pub status: Status,
-    pub keys: Keys,
+    pub keys: BTreeMap<String, KeyBinding>,
+    pub actions: BTreeMap<String, Action>,
+    pub hooks: Hooks,
+    pub openers: Vec<Opener>,
     pub style: Style,
     pub files: Vec<Files>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Segments which can be listed in the `left`, `center` and `right` parts of the status bar.
+pub const STATUS_SEGMENTS: &[&str] = &[
+    "commit_count",
+    "file_watcher_count",
+    "git_branch",
+    "git_submodules",
+    "item_count",
+    "lines_of_code",
+    "link",
+];
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Status {
     pub left: Vec<String>,
     pub center: Vec<String>,
     pub right: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Keys {
-    pub l: String,
-    pub up: String,
-    pub k: String,
-    pub down: String,
-    pub j: String,
-    pub left: String,
-    #[serde(rename = ";")]
-    pub semicolon: String,
-    pub right: String,
-    pub a: String,
-    pub f: String,
-    pub n: String,
-    #[serde(rename = "N")]
-    pub shift_n: String,
-    #[serde(rename = "V")]
-    pub shift_v: String,
-    pub e: String,
-    #[serde(rename = "E")]
-    pub shift_e: String,
-    pub s: String,
-    #[serde(rename = "S")]
-    pub shift_s: String,
-    #[serde(rename = "C-s")]
-    pub ctrl_s: String,
-    pub space: String,
-    pub y: String,
-    pub c: String,
-    pub p: String,
-    #[serde(rename = "C")]
-    pub shift_c: String,
-    #[serde(rename = "T")]
-    pub shift_t: String,
-    pub escape: String,
-    #[serde(rename = ":")]
-    pub colon: String,
-    pub q: String,
-    #[serde(rename = "Q")]
-    pub shift_q: String,
-    pub i: String,
-    pub o: String,
-    pub r: String,
-    #[serde(rename = "/")]
-    pub slash: String,
-    #[serde(rename = "?")]
-    pub question_mark: String,
-    #[serde(rename = "I")]
-    pub shift_i: String,
-    #[serde(rename = "D")]
-    pub shift_d: String,
-    #[serde(rename = "L")]
-    pub shift_l: String,
-    #[serde(rename = "C-r")]
-    pub ctrl_r: String,
-    pub g: Goto,
-}
-
-#[derive(Debug, Deserialize)]
-pub struct Goto {
-    pub g: String,
-    pub e: String,
-}
-
-#[derive(Debug, Deserialize)]
+/// Action bound to a key in the `[keys]` table, an empty action leaves the key unbound.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum KeyBinding {
+    Action(String),
+    /// Bindings for the key pressed next e.g. `g = { g = "top", e = "bottom" }`.
+    Sequence(BTreeMap<String, KeyBinding>),
+}
+
+/// Program from the `[[openers]]` list used to open files matching its mime types or globs.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Opener {
+    /// Name shown in the "Open with" menu, defaults to the command.
+    pub name: String,
+    /// Mime types like `image/png`, `image/*` or `*`.
+    pub mime: Vec<String>,
+    /// File name patterns like `*.rs` or `Makefile`.
+    pub globs: Vec<String>,
+    /// Shell command where `%f` is the file, it is appended when there is no `%f`.
+    pub command: String,
+    /// Runs in the terminal with the interface suspended instead of in the background.
+    pub terminal: bool,
+}
+
+impl Opener {
+    pub fn name(&self) -> &str {
+        if self.name.is_empty() {
+            &self.command
+        } else {
+            &self.name
+        }
+    }
+}
+
+/// Events which can run a hook command.
+pub const HOOKS: &[&str] = &[
+    "on_cd",
+    "on_open",
+    "on_paste",
+    "on_quit",
+    "on_select",
+    "on_trash",
+];
+
+/// Shell commands run in the background when an event happens, empty ones are skipped.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub on_cd: String,
+    pub on_open: String,
+    pub on_paste: String,
+    pub on_quit: String,
+    pub on_select: String,
+    pub on_trash: String,
+}
+
+impl Hooks {
+    /// Returns the command of a hook from [`HOOKS`] if one is set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let command = match name {
+            "on_cd" => &self.on_cd,
+            "on_open" => &self.on_open,
+            "on_paste" => &self.on_paste,
+            "on_quit" => &self.on_quit,
+            "on_select" => &self.on_select,
+            "on_trash" => &self.on_trash,
+            _ => return None,
+        };
+        Some(command.as_str()).filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// Shell command from the `[actions]` table which can be bound to a key like a command
+/// or run with `action <name>`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Action {
+    /// Command template where `%f` is the selected file, `%F` the marked files,
+    /// `%d` the selected directory and `%r` the root directory.
+    pub command: String,
+    pub mode: ShellMode,
+    /// Refresh the file tree after the command exits.
+    pub refresh: bool,
+    /// Ask before running the command.
+    pub confirm: bool,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action {
+            command: String::new(),
+            mode: ShellMode::default(),
+            refresh: true,
+            confirm: false,
+        }
+    }
+}
+
+/// Keys files can be sorted by with `sort.by` or the `sort` command.
+pub const SORT_KEYS: &[&str] = &[
+    "ctime",
+    "extension",
+    "git",
+    "mtime",
+    "name",
+    "natural",
+    "size",
+];
+
+/// Order of the files listed in a directory.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Sort {
+    pub by: SortKey,
+    pub reverse: bool,
+    pub directories_first: bool,
+    pub case_sensitive: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Sort {
+            by: SortKey::default(),
+            reverse: false,
+            directories_first: true,
+            case_sensitive: false,
+        }
+    }
+}
+
+/// Sizes and times are sorted from the largest and newest, ties are broken by name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    Name,
+    /// Like `Name` but numbers compare by value so `file2` comes before `file10`.
+    #[default]
+    Natural,
+    Size,
+    /// Modification time.
+    Mtime,
+    /// Status change time, local files only.
+    Ctime,
+    Extension,
+    /// Conflicted, modified, staged and untracked files come before clean and ignored ones.
+    Git,
+}
+
+/// How a shell command is run, see the `sh`, `sh-background` and `sh-capture` commands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellMode {
+    /// The interface is suspended until the command exits.
+    #[default]
+    Interactive,
+    /// Output is discarded and the exit status is shown when the command finishes.
+    Background,
+    /// Output is shown in a pane below the file tree.
+    Capture,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Style {
     pub default: InterfaceStyle,
     pub cursor_line: InterfaceStyle,
@@ -113,16 +290,20 @@ pub struct Style {
     pub document: FilesStyle,
     pub link: FilesStyle,
     pub executable: FilesStyle,
+    /// Applied on top of the file style of files inside an archive.
+    pub archive_member: InterfaceStyle,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct InterfaceStyle {
     pub fg: String,
     pub bg: String,
     pub modifiers: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct FilesStyle {
     pub icon: String,
     pub fg: String,
@@ -130,28 +311,410 @@ pub struct FilesStyle {
     pub modifiers: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Files {
     pub extensions: Vec<String>,
     pub style: String,
 }
 
+impl Style {
+    /// Returns the file style with the name used in `[[files]]` rules.
+    pub fn files_style(&self, name: &str) -> Option<&FilesStyle> {
+        match name {
+            "directory" => Some(&self.directory),
+            "file" => Some(&self.file),
+            "archive" => Some(&self.archive),
+            "video" => Some(&self.video),
+            "audio" => Some(&self.audio),
+            "image" => Some(&self.image),
+            "document" => Some(&self.document),
+            "link" => Some(&self.link),
+            "executable" => Some(&self.executable),
+            _ => None,
+        }
+    }
+}
+
+impl InterfaceStyle {
+    pub fn style(&self) -> style::Style {
+        build_style(&self.fg, &self.bg, &self.modifiers)
+    }
+}
+
+impl FilesStyle {
+    pub fn style(&self) -> style::Style {
+        build_style(&self.fg, &self.bg, &self.modifiers)
+    }
+}
+
+/// Empty colours are left unset so the style of the surrounding text shows through.
+/// Invalid values are ignored here, they are reported by `fm --check-config`.
+fn build_style(fg: &str, bg: &str, modifiers: &[String]) -> style::Style {
+    let mut style = style::Style::default();
+    if let (false, Some(color)) = (fg.is_empty(), parse_color(fg)) {
+        style = style.fg(color);
+    }
+    if let (false, Some(color)) = (bg.is_empty(), parse_color(bg)) {
+        style = style.bg(color);
+    }
+    for modifier in modifiers {
+        if let Some(modifier) = parse_modifier(modifier) {
+            style = style.add_modifier(modifier);
+        }
+    }
+    style
+}
+
+/// Parses a colour name, a `#rrggbb` hex value or a 256 colour palette index.
+/// An empty string or `default` leaves the terminal colour unchanged.
+pub fn parse_color(text: &str) -> Option<Color> {
+    let color = match text.to_lowercase().replace(['_', '-'], "").as_str() {
+        "" | "default" | "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        text => match text.strip_prefix('#') {
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let value = u32::from_str_radix(hex, 16).ok()?;
+                Color::Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8)
+            }
+            Some(_) => return None,
+            None => Color::Indexed(text.parse().ok()?),
+        },
+    };
+    Some(color)
+}
+
+pub fn parse_modifier(text: &str) -> Option<Modifier> {
+    let modifier = match text.to_lowercase().replace('-', "_").as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" => Modifier::UNDERLINED,
+        "slow_blink" => Modifier::SLOW_BLINK,
+        "rapid_blink" => Modifier::RAPID_BLINK,
+        "reversed" => Modifier::REVERSED,
+        "hidden" => Modifier::HIDDEN,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+/// Returns the program configuration directory e.g. `$XDG_CONFIG_HOME/fm`.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or(home_dir().join(".config"))
+        .join("fm")
+}
+
+/// Returns the program data directory e.g. `$XDG_DATA_HOME/fm`.
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or(home_dir().join(".local/share"))
+        .join("fm")
+}
+
+/// Returns the directory containing new file templates keyed by extension.
+pub fn templates_dir() -> PathBuf {
+    config_dir().join("templates")
+}
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or(format!("/home/{}", whoami::username()).into())
+}
+
+/// Reads a single configuration file, missing keys are taken from the built-in defaults.
 pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config> {
-    let mut file = File::open(path)?;
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+    let mut config = default_value();
+    merge(&mut config, read_value(path.as_ref())?);
+    Ok(config.try_into()?)
+}
+
+/// Loads all configuration layers on top of the built-in defaults.
+/// Keys which run commands are ignored in a project configuration which isn't trusted.
+pub fn load_config(user_config: &Path, root: &Path) -> Result<Config> {
+    load_layers(user_config, root, &trust_file())
+}
+
+fn load_layers(user_config: &Path, root: &Path, trust_file: &Path) -> Result<Config> {
+    let project = project_config(root);
+    let mut config = default_value();
+    for path in config_layers(user_config, root) {
+        let mut value = read_value(&path)?;
+        if Some(&path) == project.as_ref() && !is_trusted(trust_file, &path) {
+            if let Value::Table(table) = &mut value {
+                table.retain(|key, _| !TRUSTED_KEYS.contains(&key));
+            }
+        }
+        merge(&mut config, value);
+    }
+    Ok(config.try_into()?)
+}
+
+/// Returns the nearest project configuration in the root directory or its parents.
+pub fn project_config(root: &Path) -> Option<PathBuf> {
+    root.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.is_file())
+}
+
+/// Returns the project configuration if it sets keys which are ignored until it is trusted.
+pub fn untrusted_config(root: &Path) -> Option<PathBuf> {
+    let path = project_config(root)?;
+    let Ok(Value::Table(table)) = read_value(&path) else {
+        return None;
+    };
+    let restricted = TRUSTED_KEYS.iter().any(|key| table.contains_key(*key));
+    Some(path).filter(|path| restricted && !is_trusted(&trust_file(), path))
+}
 
-    let config: Config = toml::from_str(&contents)?;
-    Ok(config)
+/// File listing the trusted project configurations as `<sha256> <path>` lines.
+fn trust_file() -> PathBuf {
+    data_dir().join("trusted")
+}
+
+/// A configuration is trusted while its contents are the same as when it was trusted.
+fn is_trusted(trust_file: &Path, path: &Path) -> bool {
+    let Ok(entry) = trust_entry(path) else {
+        return false;
+    };
+    fs::read_to_string(trust_file).is_ok_and(|contents| contents.lines().any(|line| line == entry))
+}
+
+/// Trusts the current contents of a project configuration.
+pub fn trust(path: &Path) -> Result<()> {
+    trust_in(&trust_file(), path)
+}
+
+fn trust_in(trust_file: &Path, path: &Path) -> Result<()> {
+    let entry = trust_entry(path)?;
+    let path = entry
+        .split_once(' ')
+        .map_or("", |(_, path)| path)
+        .to_owned();
+    let contents = fs::read_to_string(trust_file).unwrap_or_default();
+    // Earlier contents of the same file are no longer trusted.
+    let mut lines: Vec<&str> = contents
+        .lines()
+        .filter(|line| line.split_once(' ').is_none_or(|(_, other)| other != path))
+        .collect();
+    lines.push(&entry);
+    if let Some(dir) = trust_file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(trust_file, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+fn trust_entry(path: &Path) -> Result<String> {
+    let path = fs::canonicalize(path)?;
+    let digest = Sha256::digest(fs::read(&path)?);
+    let hash: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!("{} {}", hash, path.display()))
+}
+
+/// Returns the existing configuration files from lowest to highest precedence:
+/// the system config, the user config and the nearest project config.
+pub fn config_layers(user_config: &Path, root: &Path) -> Vec<PathBuf> {
+    let mut layers = vec![];
+    if Path::new(SYSTEM_CONFIG).is_file() {
+        layers.push(PathBuf::from(SYSTEM_CONFIG));
+    }
+    if user_config.is_file() {
+        layers.push(user_config.to_owned());
+    }
+    if let Some(project_config) = project_config(root) {
+        layers.push(project_config);
+    }
+    layers
+}
+
+fn default_value() -> Value {
+    let table: toml::Table = DEFAULT_CONFIG
+        .parse()
+        .expect("built-in configuration is invalid");
+    Value::Table(table)
+}
+
+fn read_value(path: &Path) -> Result<Value> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    let table: toml::Table = contents
+        .parse()
+        .with_context(|| format!("could not parse {}", path.display()))?;
+    Ok(Value::Table(table))
+}
+
+/// Removes the key table older versions copied to the user configuration while it is unchanged,
+/// the rest of the file is kept as is. Returns whether the table was removed.
+pub fn migrate_keys(user_config: &Path) -> Result<bool> {
+    if !user_config.is_file() {
+        return Ok(false);
+    }
+    let Value::Table(table) = read_value(user_config)? else {
+        return Ok(false);
+    };
+    let legacy: toml::Table = LEGACY_KEYS.parse().expect("legacy key table is invalid");
+    if table.get("keys").is_none() || table.get("keys") != legacy.get("keys") {
+        return Ok(false);
+    }
+    let mut document: toml_edit::DocumentMut = fs::read_to_string(user_config)?.parse()?;
+    document.remove("keys");
+    fs::write(user_config, document.to_string())?;
+    Ok(true)
+}
+
+/// Merges tables recursively, any other value in the overlay replaces the base value.
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base) => merge(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
 }
 
 impl Config {
-    pub fn set_key(&mut self, key: &str, value: &str) -> Result<()> {
-        if key == "show_hidden" {
-            self.show_hidden = value == "true";
+    /// Returns the style of the first `[[files]]` rule listing the extension of the path.
+    pub fn file_style(&self, path: &Path) -> Option<&FilesStyle> {
+        let extension = path.extension()?.to_str()?;
+        let rule = self
+            .files
+            .iter()
+            .find(|rule| rule.extensions.iter().any(|item| item == extension))?;
+        self.style.files_style(&rule.style)
+    }
+
+    /// Applies a `set` expression: `key=value` assigns a value, `key!` toggles a boolean,
+    /// `key?` returns the current value and a bare `key` enables a boolean.
+    /// Nested keys are separated by dots e.g. `style.directory.fg=cyan`.
+    pub fn set(&mut self, expression: &str) -> Result<Option<String>, Error> {
+        if let Some(key) = expression.strip_suffix('?') {
+            return Ok(Some(format!("{}={}", key, self.get_key(key)?)));
+        }
+        if let Some((key, value)) = expression.split_once('=') {
+            self.set_key(key.trim(), value.trim())?;
+        } else if let Some(key) = expression.strip_suffix('!') {
+            let value = self.get_bool(key)?;
+            self.set_value(key, Value::Boolean(!value))?;
+        } else {
+            self.get_bool(expression)?;
+            self.set_value(expression, Value::Boolean(true))?;
         }
+        Ok(None)
+    }
+
+    fn get_bool(&self, key: &str) -> Result<bool, Error> {
+        match self.get_key(key)? {
+            Value::Boolean(value) => Ok(value),
+            value => bail!("{} is not a boolean but {}", key, value.type_str()),
+        }
+    }
+
+    pub fn get_key(&self, key: &str) -> Result<Value, Error> {
+        let mut root = Value::try_from(self)?;
+        Ok(lookup(&mut root, key)?.clone())
+    }
+
+    /// Sets a value parsed according to the type of the current value.
+    /// Strings don't need quotes and arrays of strings can be given as a comma separated list.
+    pub fn set_key(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let current = self.get_key(key)?;
+        let value = match parse_value(value) {
+            Some(value) if value.same_type(&current) => value,
+            _ if current.is_str() => Value::String(value.to_owned()),
+            _ if current.is_array() => Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_owned()))
+                    .collect(),
+            ),
+            _ => bail!("invalid value for {}: expected {}", key, current.type_str()),
+        };
+        self.set_value(key, value)
+    }
+
+    fn set_value(&mut self, key: &str, value: Value) -> Result<(), Error> {
+        let mut root = Value::try_from(&*self)?;
+        *lookup(&mut root, key)? = value;
+        *self = root
+            .try_into()
+            .map_err(|error| anyhow!("invalid value for {}: {}", key, error))?;
         Ok(())
     }
+
+    /// Returns the dotted names of all keys which can be set.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        if let Ok(root) = Value::try_from(self) {
+            collect_keys(&root, "", &mut keys);
+        }
+        keys.sort();
+        keys
+    }
+}
+
+fn lookup<'v>(root: &'v mut Value, key: &str) -> Result<&'v mut Value, Error> {
+    let mut value = root;
+    for name in key.split('.') {
+        let next = match value {
+            Value::Table(table) => table.get_mut(name),
+            Value::Array(array) => name
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| array.get_mut(index)),
+            _ => None,
+        };
+        value = next.ok_or_else(|| anyhow!("unknown configuration key: {}", key))?;
+    }
+    Ok(value)
+}
+
+/// Parses a TOML value e.g. `2`, `true` or `["zsh", "-c"]`.
+fn parse_value(text: &str) -> Option<Value> {
+    let mut table: toml::Table = format!("value = {}", text).parse().ok()?;
+    table.remove("value")
+}
+
+fn collect_keys(value: &Value, prefix: &str, keys: &mut Vec<String>) {
+    if let Value::Table(table) = value {
+        for (name, value) in table {
+            let key = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", prefix, name)
+            };
+            if value.is_table() {
+                collect_keys(value, &key, keys);
+            } else {
+                keys.push(key);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -162,4 +725,164 @@ mod tests {
     fn test_read_config() {
         let _ = read_config("./desktop/config.toml").unwrap();
     }
+
+    #[test]
+    fn test_layers() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let project = dir.join("project/src");
+        fs::create_dir_all(&project).unwrap();
+        let user_config = dir.join("config.toml");
+        fs::write(
+            &user_config,
+            "show_hidden = true\n[style.directory]\nfg = \"cyan\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("project").join(PROJECT_CONFIG),
+            "indent_spaces = 2\n",
+        )
+        .unwrap();
+
+        let config = load_config(&user_config, &project).unwrap();
+        assert!(config.show_hidden);
+        assert_eq!(config.indent_spaces, 2);
+        assert_eq!(config.style.directory.fg, "cyan");
+        let defaults: Config = default_value().try_into().unwrap();
+        assert_eq!(config.style.directory.icon, defaults.style.directory.icon);
+        assert_eq!(config.shell, vec!["zsh", "-c"]);
+
+        let config = load_config(&dir.join("missing.toml"), dir).unwrap();
+        assert!(!config.show_hidden);
+        assert_eq!(config.indent_spaces, 3);
+
+        // The key table once copied to every user configuration is removed unless it was edited.
+        let binding = |config: &Config| match config.keys.get(";") {
+            Some(KeyBinding::Action(action)) => action.clone(),
+            _ => String::new(),
+        };
+        fs::write(
+            &user_config,
+            format!("# mine\nmouse = true\n{}", LEGACY_KEYS),
+        )
+        .unwrap();
+        assert!(migrate_keys(&user_config).unwrap());
+        assert_eq!(
+            fs::read_to_string(&user_config).unwrap(),
+            "# mine\nmouse = true\n"
+        );
+        assert!(!migrate_keys(&user_config).unwrap());
+        let config = load_config(&user_config, dir).unwrap();
+        assert_eq!(binding(&config), "root_down");
+        let edited = format!("{}x = \"quit\"\n", LEGACY_KEYS);
+        fs::write(&user_config, &edited).unwrap();
+        assert!(!migrate_keys(&user_config).unwrap());
+        assert_eq!(fs::read_to_string(&user_config).unwrap(), edited);
+        let config = load_config(&user_config, dir).unwrap();
+        assert_eq!(binding(&config), "expand");
+        assert!(!migrate_keys(&dir.join("missing.toml")).unwrap());
+    }
+
+    #[test]
+    fn test_trust() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let trust_file = dir.join("trusted");
+        let project_config = dir.join(PROJECT_CONFIG);
+        fs::write(
+            &project_config,
+            "indent_spaces = 2\nedit_tabs = \"-p\"\nshell = [\"sh\", \"-c\"]\n[hooks]\non_cd = \"make\"\n",
+        )
+        .unwrap();
+        let missing = dir.join("missing.toml");
+
+        let config = load_layers(&missing, dir, &trust_file).unwrap();
+        assert_eq!(config.indent_spaces, 2);
+        assert_eq!(config.shell, vec!["zsh", "-c"]);
+        assert_eq!(config.edit_tabs, "");
+        assert_eq!(config.hooks.get("on_cd"), None);
+
+        trust_in(&trust_file, &project_config).unwrap();
+        trust_in(&trust_file, &project_config).unwrap();
+        assert_eq!(fs::read_to_string(&trust_file).unwrap().lines().count(), 1);
+        let config = load_layers(&missing, dir, &trust_file).unwrap();
+        assert_eq!(config.shell, vec!["sh", "-c"]);
+        assert_eq!(config.edit_tabs, "-p");
+        assert_eq!(config.hooks.get("on_cd"), Some("make"));
+
+        // Changing a trusted file revokes the trust.
+        fs::write(&project_config, "[hooks]\non_cd = \"rm -rf ~\"\n").unwrap();
+        let config = load_layers(&missing, dir, &trust_file).unwrap();
+        assert_eq!(config.hooks.get("on_cd"), None);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut config = read_config("./desktop/config.toml").unwrap();
+        config.set("icon_spacing=3").unwrap();
+        assert_eq!(config.icon_spacing, 3);
+        config.set("show_hidden!").unwrap();
+        assert!(config.show_hidden);
+        config.set("show_hidden!").unwrap();
+        assert!(!config.show_hidden);
+        config.set("mouse").unwrap();
+        assert!(config.mouse);
+        config.set("style.directory.fg=cyan").unwrap();
+        assert_eq!(config.style.directory.fg, "cyan");
+        config.set("info=git, size").unwrap();
+        assert_eq!(config.info, vec!["git", "size"]);
+        config.set(r#"shell=["bash", "-c"]"#).unwrap();
+        assert_eq!(config.shell, vec!["bash", "-c"]);
+        config.set("files.0.style=image").unwrap();
+        assert_eq!(config.files[0].style, "image");
+        assert_eq!(
+            config.set("indent_spaces?").unwrap(),
+            Some("indent_spaces=3".into())
+        );
+        assert!(config.set("icon_spacing=wide").is_err());
+        assert!(config.set("icon_spacing=300").is_err());
+        assert!(config.set("selection_symbol!").is_err());
+        assert!(config.set("bogus=1").is_err());
+        config.set("sort.by=size").unwrap();
+        assert_eq!(config.sort.by, SortKey::Size);
+        assert!(config.set("sort.by=random").is_err());
+        assert!(config.keys().contains(&"keys.C-s".to_owned()));
+    }
+
+    #[test]
+    fn test_actions() {
+        let config: Config = toml::from_str(
+            "[actions]\ndeploy = { command = \"make deploy\", mode = \"capture\" }\n",
+        )
+        .unwrap();
+        let deploy = &config.actions["deploy"];
+        assert_eq!(deploy.mode, ShellMode::Capture);
+        assert!(deploy.refresh);
+        assert!(!deploy.confirm);
+        let invalid = "[actions]\nls = { command = \"ls\", mode = \"detached\" }\n";
+        assert!(toml::from_str::<Config>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let mut config: Config = toml::from_str("[hooks]\non_cd = \"pwd\"\n").unwrap();
+        assert_eq!(config.hooks.get("on_cd"), Some("pwd"));
+        assert_eq!(config.hooks.get("on_open"), None);
+        assert_eq!(config.hooks.get("on_rename"), None);
+        for name in HOOKS {
+            config.set_key(&format!("hooks.{}", name), "true").unwrap();
+            assert_eq!(config.hooks.get(name), Some("true"));
+        }
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color(""), Some(Color::Reset));
+        assert_eq!(parse_color("light_blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("#ff8000"), Some(Color::Rgb(255, 128, 0)));
+        assert_eq!(parse_color("208"), Some(Color::Indexed(208)));
+        assert_eq!(parse_color("#ff80"), None);
+        assert_eq!(parse_color("256"), None);
+        assert_eq!(parse_color("purple"), None);
+    }
 }
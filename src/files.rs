@@ -1,40 +1,36 @@
 #![allow(unused)]
 use std::cell::RefCell;
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
+use std::io::Write;
 use std::{fs, io, path, process};
 
 use anyhow::{anyhow, Error, Result};
-use git2::{DiffOptions, Repository};
+use git2::{DiffOptions, Repository, Status, StatusOptions};
 use tui::style::{Color, Style};
 use tui::text::{Span, Spans};
 use tui::widgets::ListItem;
 
+use crate::archive::{self, Member};
+use crate::config::{Sort, SortKey};
+use crate::vfs::{Stat, Vfs};
 use crate::Config;
 
 #[derive(Clone, Debug)]
 pub struct File {
     pub path: PathBuf,
-    pub metadata: fs::Metadata,
-    pub metadata_extra: fs::Metadata,
+    pub stat: Stat,
     pub descendants: Vec<File>,
+    /// Set for virtual files inside an archive.
+    pub member: Option<Member>,
 }
 
+/// Files are ordered with the default [`Sort`], see [`sort`] for the configured one.
 impl Ord for File {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.metadata.is_dir() && !other.metadata.is_dir() {
-            Ordering::Less
-        } else if other.metadata.is_dir() && !self.metadata.is_dir() {
-            Ordering::Greater
-        } else {
-            // TODO: Can I not use the lossy conversion here in order to lowercase a path?
-            self.path
-                .to_string_lossy()
-                .to_lowercase()
-                .cmp(&other.path.to_string_lossy().to_lowercase())
-        }
+        compare(self, other, &Sort::default(), &HashMap::new())
     }
 }
 
@@ -131,8 +127,12 @@ pub fn apply_to_all<'a>(
 }
 
 impl File {
-    pub fn is_empty(&self) -> bool {
-        self.metadata.is_dir() && fs::read_dir(&self.path).expect("could not read dir").count() == 0
+    pub fn is_empty(&self, vfs: &dyn Vfs) -> bool {
+        self.member.is_none() && self.stat.is_dir && vfs.list(&self.path).expect("could not read dir").is_empty()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.stat.is_dir
     }
 
     fn format_file_size(size: u64) -> String {
@@ -158,14 +158,16 @@ impl File {
         }
     }
 
-    pub fn info_count<'a>(&self) -> Result<Span<'a>, Error> {
-        if self.metadata.is_dir() {
+    pub fn info_count<'a>(&self, vfs: &dyn Vfs) -> Result<Span<'a>, Error> {
+        if let Some(member) = &self.member {
+            let size = if member.is_dir { String::new() } else { Self::format_file_size(member.size) };
+            return Ok(Span::raw(size))
+        }
+        if self.stat.is_dir {
             let mut count = 0;
-            match fs::read_dir(self.path.clone()) {
+            match vfs.list(&self.path) {
                 Ok(entries) => {
-                    for entry in entries {
-                        count += 1;
-                    }
+                    count = entries.len();
                 }
                 Err(error) => {
                     if error.kind() == io::ErrorKind::PermissionDenied {
@@ -178,7 +180,7 @@ impl File {
                 Style::default().fg(Color::Blue),
             ))
         } else {
-            Ok(Span::styled(Self::format_file_size(self.metadata.len()), Style::default()))
+            Ok(Span::styled(Self::format_file_size(self.stat.len), Style::default()))
         }
     }
 
@@ -186,76 +188,245 @@ impl File {
         FileIteratorRef::new(self)
     }
 
-    pub fn is_video(&self) -> bool {
-        if let Some(extension) = self.path.extension() {
-            if extension == "mkv"
-                || extension == "mp4"
-                || extension == "webm"
-                || extension == "wav"
-                || extension == "avi"
-            {
-                return true
-            }
-        }
-        false
+    pub fn is_executable(&self) -> bool {
+        self.stat.is_executable()
+    }
+
+    /// Returns whether the file is an archive whose contents can be listed in the tree.
+    pub fn is_archive(&self) -> bool {
+        self.member.is_none() && self.stat.is_file && archive::kind(&self.path).is_some()
+    }
+
+    pub fn count(&self) -> u32 {
+        let mut count = 0;
+        count_files(self, &mut count);
+        count
     }
+}
 
-    pub fn is_audio(&self) -> bool {
-        if let Some(extension) = self.path.extension() {
-            if extension == "mp3" {
-                return true
+/// Expands shell-style braces in a name e.g. `src/{lib,main}.rs` becomes `src/lib.rs` and
+/// `src/main.rs`. Nested braces are expanded recursively and unbalanced braces are kept as is.
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some(open) = pattern.find('{') else {
+        return vec![pattern.to_owned()]
+    };
+    // Find the matching closing brace and the top-level commas in between.
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut close = None;
+    for (index, c) in pattern[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + index);
+                    break
+                }
             }
+            ',' if depth == 1 => commas.push(open + index),
+            _ => {}
         }
-        false
-    }
-
-    pub fn is_image(&self) -> bool {
-        if let Some(extension) = self.path.extension() {
-            if extension == "jpg"
-                || extension == "jpeg"
-                || extension == "png"
-                || extension == "svg"
-                || extension == "webp"
-                || extension == "gif"
-            {
-                return true
+    }
+    let Some(close) = close else {
+        return vec![pattern.to_owned()]
+    };
+    let prefix = &pattern[..open];
+    let suffix = &pattern[close + 1..];
+    if commas.is_empty() {
+        // Braces without alternatives are literal, only the suffix can still expand.
+        return expand_braces(suffix)
+            .into_iter()
+            .map(|rest| format!("{}{}", &pattern[..=close], rest))
+            .collect()
+    }
+    let mut bounds = vec![open];
+    bounds.extend(commas);
+    bounds.push(close);
+    let mut expanded = vec![];
+    for window in bounds.windows(2) {
+        let alternative = &pattern[window[0] + 1..window[1]];
+        for alternative in expand_braces(alternative) {
+            for rest in expand_braces(suffix) {
+                expanded.push(format!("{}{}{}", prefix, alternative, rest));
             }
         }
-        false
     }
+    expanded
+}
 
-    pub fn is_archive(&self) -> bool {
-        if let Some(extension) = self.path.extension() {
-            if extension == "zip" || extension == "tar" || extension == "gz" {
-                return true
-            }
+/// Rank of files without a status in the `git` sort order.
+const GIT_CLEAN: u8 = 4;
+
+/// Sorts the files of a directory, `ranks` from [`git_ranks`] are only used by the `git` key.
+pub fn sort(files: &mut [File], sort: &Sort, ranks: &HashMap<PathBuf, u8>) {
+    files.sort_by(|a, b| compare(a, b, sort, ranks));
+}
+
+fn compare(a: &File, b: &File, sort: &Sort, ranks: &HashMap<PathBuf, u8>) -> Ordering {
+    if sort.directories_first && a.is_dir() != b.is_dir() {
+        return b.is_dir().cmp(&a.is_dir());
+    }
+    let name = |file: &File| {
+        let name = file.path.file_name().unwrap_or_default().to_string_lossy();
+        if sort.case_sensitive {
+            name.into_owned()
+        } else {
+            name.to_lowercase()
         }
-        false
-    }
-
-    pub fn is_document(&self) -> bool {
-        if let Some(extension) = self.path.extension() {
-            if extension == "epub"
-                || extension == "pdf"
-                || extension == "mobi"
-                || extension == "ipynb"
-                || extension == "azw"
-            {
-                return true
+    };
+    let (a_name, b_name) = (name(a), name(b));
+    let rank = |file: &File| ranks.get(&file.path).copied().unwrap_or(GIT_CLEAN);
+    let ordering = match sort.by {
+        SortKey::Name => a_name.cmp(&b_name),
+        SortKey::Natural => Ordering::Equal,
+        SortKey::Size => b.stat.len.cmp(&a.stat.len),
+        SortKey::Mtime => b.stat.modified.cmp(&a.stat.modified),
+        SortKey::Ctime => b.stat.changed.cmp(&a.stat.changed),
+        SortKey::Extension => Path::new(&a_name)
+            .extension()
+            .cmp(&Path::new(&b_name).extension()),
+        SortKey::Git => rank(a).cmp(&rank(b)),
+    }
+    .then_with(|| natural_cmp(&a_name, &b_name))
+    // Names differing only in case keep a stable order.
+    .then_with(|| a.path.cmp(&b.path));
+    if sort.reverse {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// Compares strings with runs of digits compared by their value, so `file2` comes before
+/// `file10`. Numbers with leading zeros come after the same number without them.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let a_end = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+                let b_end = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+                let (a_digits, b_digits) = (&a[..a_end], &b[..b_end]);
+                let a_value = a_digits.trim_start_matches('0');
+                let b_value = b_digits.trim_start_matches('0');
+                let ordering = a_value
+                    .len()
+                    .cmp(&b_value.len())
+                    .then_with(|| a_value.cmp(b_value))
+                    .then_with(|| a_digits.len().cmp(&b_digits.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a = &a[a_end..];
+                b = &b[b_end..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
             }
         }
-        false
     }
+}
 
-    pub fn is_executable(&self) -> bool {
-        self.metadata.permissions().mode() & 0o111 != 0
+/// Ranks the files of the repository containing a local directory for the `git` sort key:
+/// conflicted, modified, staged, untracked and ignored. Clean files have no rank and
+/// directories take the rank of the most changed file below them.
+pub fn git_ranks(dir: &Path) -> HashMap<PathBuf, u8> {
+    let mut ranks = HashMap::new();
+    let Ok(repo) = Repository::discover(dir) else {
+        return ranks
+    };
+    let Some(workdir) = repo.workdir() else {
+        return ranks
+    };
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .include_ignored(true)
+        .recurse_untracked_dirs(false)
+        .recurse_ignored_dirs(false);
+    let Ok(statuses) = repo.statuses(Some(&mut options)) else {
+        return ranks
+    };
+    let modified =
+        Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE;
+    let staged = Status::INDEX_NEW
+        | Status::INDEX_MODIFIED
+        | Status::INDEX_DELETED
+        | Status::INDEX_RENAMED
+        | Status::INDEX_TYPECHANGE;
+    for entry in statuses.iter() {
+        let status = entry.status();
+        let rank = if status.is_conflicted() {
+            0
+        } else if status.intersects(modified) {
+            1
+        } else if status.intersects(staged) {
+            2
+        } else if status.is_wt_new() {
+            3
+        } else if status.is_ignored() {
+            5
+        } else {
+            continue
+        };
+        // Untracked and ignored directories end with a slash.
+        let Some(path) = entry.path() else { continue };
+        let path = workdir.join(path.trim_end_matches('/'));
+        if rank > GIT_CLEAN {
+            ranks.entry(path).or_insert(rank);
+            continue
+        }
+        for ancestor in path.ancestors().take_while(|ancestor| ancestor.starts_with(workdir)) {
+            let current = ranks.entry(ancestor.to_owned()).or_insert(rank);
+            *current = rank.min(*current);
+        }
     }
+    ranks
+}
 
-    pub fn count(&self) -> u32 {
-        let mut count = 0;
-        count_files(self, &mut count);
-        count
+/// Git ranks of the repositories a tree is read from, the statuses of each repository are
+/// only read once.
+#[derive(Default)]
+pub struct GitRanks {
+    /// Ranks keyed by the git directory, an empty path for directories outside a repository.
+    repositories: HashMap<PathBuf, HashMap<PathBuf, u8>>,
+}
+
+impl GitRanks {
+    /// Returns the ranks of the repository containing a local directory, see [`git_ranks`].
+    pub fn get(&mut self, dir: &Path) -> &HashMap<PathBuf, u8> {
+        let ceiling_dirs: [&Path; 0] = [];
+        let repository = Repository::discover_path(dir, ceiling_dirs).unwrap_or_default();
+        self.repositories.entry(repository).or_insert_with(|| git_ranks(dir))
+    }
+}
+
+/// Creates a new empty file along with any missing parent directories.
+/// If the templates directory contains a file named after the extension (e.g. `rs`),
+/// its contents are used for the new file.
+pub fn create_file(path: &Path, templates: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    if let Some(extension) = path.extension() {
+        let template = templates.join(extension);
+        if template.is_file() {
+            file.write_all(&fs::read(template)?)?;
+        }
     }
+    Ok(())
 }
 
 fn count_files(file: &File, count: &mut u32) {
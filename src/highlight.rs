@@ -0,0 +1,184 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{FontStyle, ThemeSet};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+use tui::style::{Color, Modifier, Style};
+use tui::text::Span;
+
+/// Number of lines at the start and end of a file searched for a vim or emacs modeline.
+const MODELINE_LINES: usize = 5;
+
+static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+
+fn syntaxes() -> &'static SyntaxSet {
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn themes() -> &'static ThemeSet {
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Returns the names of the built-in themes which can be used for `preview_theme`.
+pub fn theme_names() -> Vec<&'static str> {
+    themes().themes.keys().map(String::as_str).collect()
+}
+
+/// Highlights text with the syntax detected from a modeline, the file name or the first line.
+/// Returns `None` when no syntax matches or the theme doesn't exist.
+pub fn highlight(path: &Path, text: &str, theme: &str) -> Option<Vec<Vec<Span<'static>>>> {
+    let syntax = detect(path, text)?;
+    let theme = themes().themes.get(theme)?;
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut lines = vec![];
+    for line in LinesWithEndings::from(text) {
+        let ranges = highlighter.highlight_line(line, syntaxes()).ok()?;
+        let spans = ranges
+            .into_iter()
+            .map(|(style, text)| {
+                let text = text.trim_end_matches(['\n', '\r']).replace('\t', "    ");
+                Span::styled(text, span_style(style))
+            })
+            .collect();
+        lines.push(spans);
+    }
+    Some(lines)
+}
+
+/// Detects the syntax of a file, a modeline takes precedence over the extension and the
+/// extension over a shebang.
+fn detect<'s>(path: &Path, text: &str) -> Option<&'s SyntaxReference> {
+    let syntaxes = syntaxes();
+    if let Some(syntax) = modeline(text).and_then(|name| syntaxes.find_syntax_by_token(&name)) {
+        return Some(syntax);
+    }
+    let by_name = [path.extension(), path.file_name()]
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.to_str())
+        .find_map(|name| syntaxes.find_syntax_by_extension(name));
+    if by_name.is_some() {
+        return by_name;
+    }
+    syntaxes.find_syntax_by_first_line(text.lines().next()?)
+}
+
+/// Returns the file type set by a vim modeline like `vim: set ft=rust:` or an emacs modeline
+/// like `-*- mode: python -*-`.
+pub fn modeline(text: &str) -> Option<String> {
+    let lines: Vec<&str> = text.lines().collect();
+    let end = lines
+        .len()
+        .saturating_sub(MODELINE_LINES)
+        .max(MODELINE_LINES);
+    let candidates = lines
+        .iter()
+        .take(MODELINE_LINES)
+        .chain(lines.iter().skip(end));
+    for line in candidates {
+        if let Some(name) = vim_modeline(line).or_else(|| emacs_modeline(line)) {
+            return Some(name);
+        }
+    }
+    None
+}
+
+fn vim_modeline(line: &str) -> Option<String> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| *index == 0 || line[..*index].ends_with(char::is_whitespace))
+            .map(|(index, _)| index + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (name, value) = option.split_once('=')?;
+            matches!(name, "ft" | "filetype" | "syntax").then(|| value.to_owned())
+        })
+        .filter(|value| !value.is_empty())
+}
+
+fn emacs_modeline(line: &str) -> Option<String> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let variables = line[start..end].trim();
+    if !variables.contains(':') {
+        return (!variables.is_empty()).then(|| variables.to_owned());
+    }
+    variables.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+        (name.trim().eq_ignore_ascii_case("mode")).then(|| value.trim().to_owned())
+    })
+}
+
+/// Converts a highlighted style to the terminal, keeping the background of the pane.
+fn span_style(style: syntect::highlighting::Style) -> Style {
+    let color = style.foreground;
+    let mut span_style = Style::default().fg(Color::Rgb(color.r, color.g, color.b));
+    for (font_style, modifier) in [
+        (FontStyle::BOLD, Modifier::BOLD),
+        (FontStyle::ITALIC, Modifier::ITALIC),
+        (FontStyle::UNDERLINE, Modifier::UNDERLINED),
+    ] {
+        if style.font_style.contains(font_style) {
+            span_style = span_style.add_modifier(modifier);
+        }
+    }
+    span_style
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_modeline() {
+        assert_eq!(
+            modeline("# vim: set ft=python:\nx = 1"),
+            Some("python".into())
+        );
+        assert_eq!(
+            modeline("x\n// vi: ts=4 filetype=rust\n"),
+            Some("rust".into())
+        );
+        assert_eq!(
+            modeline("#!/bin/sh\n# -*- mode: ruby; indent: 2 -*-"),
+            Some("ruby".into())
+        );
+        assert_eq!(modeline("; -*- lisp -*-"), Some("lisp".into()));
+        assert_eq!(modeline("let environment: set ft=x"), None);
+        let mut text = "x\n".repeat(20);
+        text.push_str("# vim: ft=sh\n");
+        assert_eq!(modeline(&text), Some("sh".into()));
+    }
+
+    #[test]
+    fn test_detect() {
+        let name = |path: &str, text: &str| detect(Path::new(path), text).map(|s| s.name.clone());
+        assert_eq!(name("main.rs", ""), Some("Rust".into()));
+        assert_eq!(name("Makefile", ""), Some("Makefile".into()));
+        assert_eq!(
+            name("run", "#!/usr/bin/env python3\n"),
+            Some("Python".into())
+        );
+        assert_eq!(name("main.rs", "# vim: ft=python\n"), Some("Python".into()));
+        assert_eq!(name("notes", "hello\n"), None);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let lines = highlight(
+            Path::new("main.rs"),
+            "fn main() {\n\tx\n}\n",
+            "base16-ocean.dark",
+        )
+        .unwrap();
+        assert_eq!(lines.len(), 3);
+        let text: String = lines[1].iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, "    x");
+        assert!(highlight(Path::new("main.rs"), "", "missing").is_none());
+    }
+}
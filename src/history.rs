@@ -0,0 +1,191 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use fs4::fs_std::FileExt;
+use log::error;
+
+/// Maximum number of entries kept in the history file.
+pub const HISTORY_SIZE: usize = 1000;
+
+/// Each kind of prompt keeps a separate history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptKind {
+    Command,
+    Shell,
+    Search,
+    NewFile,
+}
+
+impl PromptKind {
+    /// Returns the kind of prompt the input was typed into.
+    pub fn of(input: &str) -> Self {
+        if ["sh ", "sh-background ", "sh-capture "]
+            .iter()
+            .any(|prefix| input.starts_with(prefix))
+        {
+            PromptKind::Shell
+        } else if input.starts_with("search ") {
+            PromptKind::Search
+        } else if ["new-file ", "new-dir ", "compress "]
+            .iter()
+            .any(|prefix| input.starts_with(prefix))
+        {
+            PromptKind::NewFile
+        } else {
+            PromptKind::Command
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            PromptKind::Command => "command",
+            PromptKind::Shell => "shell",
+            PromptKind::Search => "search",
+            PromptKind::NewFile => "new-file",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "command" => Some(PromptKind::Command),
+            "shell" => Some(PromptKind::Shell),
+            "search" => Some(PromptKind::Search),
+            "new-file" => Some(PromptKind::NewFile),
+            _ => None,
+        }
+    }
+}
+
+/// Command bar history persisted to a file with one `kind<TAB>input` entry per line.
+#[derive(Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<(PromptKind, String)>,
+}
+
+impl History {
+    pub fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents),
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    error!("could not read history file: {}", error);
+                }
+                vec![]
+            }
+        };
+        History {
+            path: Some(path),
+            entries,
+        }
+    }
+
+    /// Returns the entries of the given kind (or all entries) from oldest to newest.
+    pub fn entries(&self, kind: Option<PromptKind>) -> Vec<&str> {
+        self.entries
+            .iter()
+            .filter(|(entry_kind, _)| kind.is_none_or(|kind| kind == *entry_kind))
+            .map(|(_, input)| input.as_str())
+            .collect()
+    }
+
+    /// Appends the input to the history and writes it to the history file.
+    /// The file is re-read under a lock so entries from other instances are kept.
+    pub fn add(&mut self, input: &str) -> Result<(), Error> {
+        let kind = PromptKind::of(input);
+        push(&mut self.entries, kind, input);
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.lock_exclusive()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut entries = parse(&contents);
+        push(&mut entries, kind, input);
+        let mut contents = String::new();
+        for (kind, input) in &entries {
+            contents.push_str(&format!("{}\t{}\n", kind.name(), input));
+        }
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        #[allow(unstable_name_collisions)]
+        file.unlock()?;
+        self.entries = entries;
+        Ok(())
+    }
+}
+
+fn parse(contents: &str) -> Vec<(PromptKind, String)> {
+    let mut entries = vec![];
+    for line in contents.lines() {
+        if let Some((kind, input)) = line.split_once('\t') {
+            if let Some(kind) = PromptKind::from_name(kind) {
+                push(&mut entries, kind, input);
+            }
+        }
+    }
+    entries
+}
+
+/// Adds an entry as the newest one, removing any older duplicate and the oldest entries
+/// above the size limit.
+fn push(entries: &mut Vec<(PromptKind, String)>, kind: PromptKind, input: &str) {
+    entries.retain(|(entry_kind, entry)| *entry_kind != kind || entry != input);
+    entries.push((kind, input.to_owned()));
+    if entries.len() > HISTORY_SIZE {
+        entries.drain(..entries.len() - HISTORY_SIZE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_dedup() {
+        let mut history = History::default();
+        history.add("sh make test").unwrap();
+        history.add("search foo").unwrap();
+        history.add("sh-capture cargo build").unwrap();
+        history.add("sh make test").unwrap();
+        assert_eq!(
+            history.entries(Some(PromptKind::Shell)),
+            vec!["sh-capture cargo build", "sh make test"]
+        );
+        assert_eq!(
+            history.entries(Some(PromptKind::Search)),
+            vec!["search foo"]
+        );
+        assert_eq!(history.entries(None).len(), 3);
+    }
+
+    #[test]
+    fn test_prompt_kind() {
+        assert_eq!(PromptKind::of("sh-capture make"), PromptKind::Shell);
+        assert_eq!(PromptKind::of("new-dir a"), PromptKind::NewFile);
+        assert_eq!(PromptKind::of("compress a.zip"), PromptKind::NewFile);
+        assert_eq!(PromptKind::of("compress"), PromptKind::Command);
+    }
+
+    #[test]
+    fn test_history_parse() {
+        let entries = parse("shell\tsh ls\nbogus\tfoo\ncommand\tpath\nshell\tsh ls\n");
+        assert_eq!(
+            entries,
+            vec![
+                (PromptKind::Command, "path".to_owned()),
+                (PromptKind::Shell, "sh ls".to_owned())
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Error, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::KeyBinding;
+
+/// A key with its modifiers as written in the `[keys]` table e.g. `q`, `Q`, `C-r` or `space`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Shift is folded into the character so `Q` and `S-q` are the same key.
+    pub fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            KeyCode::BackTab => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::BackTab
+            }
+            code => code,
+        };
+        Key { code, modifiers }
+    }
+
+    /// Parses a key name with optional `C-` (control), `A-` or `M-` (alt) and `S-` (shift)
+    /// prefixes e.g. `C-s`, `A-left` or `f5`.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut modifiers = KeyModifiers::NONE;
+        let mut name = text;
+        while let Some((prefix, rest)) = name.split_once('-').filter(|(_, rest)| !rest.is_empty()) {
+            modifiers |= match prefix {
+                "C" => KeyModifiers::CONTROL,
+                "A" | "M" => KeyModifiers::ALT,
+                "S" => KeyModifiers::SHIFT,
+                _ => break,
+            };
+            name = rest;
+        }
+        let code = match name {
+            "space" => KeyCode::Char(' '),
+            "escape" | "esc" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            _ if name.chars().count() == 1 => KeyCode::Char(name.chars().next().unwrap_or(' ')),
+            _ => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(number @ 1..=12) => KeyCode::F(number),
+                _ => return Err(anyhow!("unknown key: {}", text)),
+            },
+        };
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Binding {
+    Action(String),
+    Sequence(HashMap<Key, Binding>),
+}
+
+/// Key bindings of the file tree built from the `[keys]` table.
+#[derive(Debug, Default)]
+pub struct Keymap {
+    bindings: HashMap<Key, Binding>,
+}
+
+impl Keymap {
+    pub fn new(keys: &BTreeMap<String, KeyBinding>) -> Result<Self, Error> {
+        Ok(Keymap {
+            bindings: parse_bindings(keys)?,
+        })
+    }
+
+    /// Returns the binding of a sequence of keys, which is itself a sequence
+    /// while more keys are needed to pick an action.
+    pub fn get(&self, keys: &[Key]) -> Option<&Binding> {
+        let (first, rest) = keys.split_first()?;
+        let mut binding = self.bindings.get(first)?;
+        for key in rest {
+            match binding {
+                Binding::Sequence(bindings) => binding = bindings.get(key)?,
+                Binding::Action(_) => return None,
+            }
+        }
+        Some(binding)
+    }
+}
+
+fn parse_bindings(keys: &BTreeMap<String, KeyBinding>) -> Result<HashMap<Key, Binding>, Error> {
+    let mut bindings = HashMap::new();
+    for (name, binding) in keys {
+        let binding = match binding {
+            KeyBinding::Action(action) if action.is_empty() => continue,
+            KeyBinding::Action(action) => Binding::Action(action.clone()),
+            KeyBinding::Sequence(keys) => Binding::Sequence(parse_bindings(keys)?),
+        };
+        bindings.insert(Key::parse(name)?, binding);
+    }
+    Ok(bindings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key() {
+        let key = |code, modifiers| Key { code, modifiers };
+        assert_eq!(
+            Key::parse("q").unwrap(),
+            key(KeyCode::Char('q'), KeyModifiers::NONE)
+        );
+        assert_eq!(Key::parse("S-q").unwrap(), Key::parse("Q").unwrap());
+        assert_eq!(
+            Key::parse("C-r").unwrap(),
+            key(KeyCode::Char('r'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Key::parse("C-A-left").unwrap(),
+            key(KeyCode::Left, KeyModifiers::CONTROL | KeyModifiers::ALT)
+        );
+        assert_eq!(
+            Key::parse("-").unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            Key::parse("C--").unwrap(),
+            key(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            Key::parse("f5").unwrap(),
+            key(KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert!(Key::parse("hyper-x").is_err());
+        assert!(Key::parse("f13").is_err());
+        assert_eq!(
+            Key::from(KeyEvent::new(KeyCode::Char('Q'), KeyModifiers::SHIFT)),
+            Key::parse("Q").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_keymap() {
+        let keys: BTreeMap<String, KeyBinding> =
+            toml::from_str("q = \"quit\"\nx = \"\"\ng = { g = \"top\" }\n").unwrap();
+        let keymap = Keymap::new(&keys).unwrap();
+        let q = Key::parse("q").unwrap();
+        let g = Key::parse("g").unwrap();
+        assert_eq!(keymap.get(&[q]), Some(&Binding::Action("quit".into())));
+        assert!(matches!(keymap.get(&[g]), Some(Binding::Sequence(_))));
+        assert_eq!(keymap.get(&[g, g]), Some(&Binding::Action("top".into())));
+        assert_eq!(keymap.get(&[g, q]), None);
+        assert_eq!(keymap.get(&[Key::parse("x").unwrap()]), None);
+    }
+}
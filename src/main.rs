@@ -8,21 +8,23 @@ use std::os::unix::net::UnixStream;
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{ExitCode, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use std::{env, fs, io, os, path, process, thread, vec};
 
 use anyhow::{anyhow, Error, Result};
-use application::{Application, SOCKET_PATH};
+use application::{Application, FileChooser, SOCKET_PATH};
 use clap::{arg, Arg, ArgAction, Command};
+use command_bar::Message;
 use config::Config;
 use crossterm::cursor::{position, Hide};
 use crossterm::event::MouseButton::{Left, Middle, Right};
 use crossterm::event::{
-    poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
-    ModifierKeyCode, MouseEvent, MouseEventKind,
+    poll, read, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture, Event, KeyCode,
+    KeyEvent, KeyModifiers, ModifierKeyCode, MouseEvent, MouseEventKind,
 };
 use crossterm::style::{self, ResetColor, Stylize};
 use crossterm::terminal::{
@@ -33,9 +35,14 @@ use crossterm::tty::IsTty;
 use crossterm::{cursor, execute, queue, terminal, ExecutableCommand, QueueableCommand};
 use files::File;
 use fs4::fs_std::FileExt;
+use history::History;
+use keymap::{Binding, Key, Keymap};
 use log::{error, info, warn};
+use output::Output;
+use session::Session;
 use subprocess::{PopenConfig, Redirection};
 use sysinfo::{ProcessRefreshKind, RefreshKind, System};
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::task;
 use tui::backend::{Backend, CrosstermBackend};
 use tui::layout::{Alignment, Constraint, Direction, Layout};
@@ -43,10 +50,27 @@ use tui::style::{Color, Modifier, Style};
 use tui::text::{Span, Spans};
 use tui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
 use tui::{Frame, Terminal};
+use vfs::{Local, Vfs};
+use views::{View, Views};
 
 mod application;
+mod archive;
+mod check;
+mod command_bar;
+mod commands;
+mod completion;
 mod config;
 pub(crate) mod files;
+mod highlight;
+mod history;
+mod keymap;
+mod opener;
+mod output;
+mod preview;
+mod session;
+mod sftp;
+mod vfs;
+mod views;
 
 #[cfg(test)]
 mod tests;
@@ -57,6 +81,12 @@ pub(crate) mod proto {
 
 type CrossTerminal = Terminal<CrosstermBackend<io::Stdout>>;
 
+/// How long to wait for input before checking for configuration reloads and command output.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often configuration files are checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line flags and arguments.
@@ -97,12 +127,39 @@ async fn main() -> Result<()> {
                 .action(ArgAction::Set)
                 .required(false),
         )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Specify config file location")
+                .action(ArgAction::Set)
+                .required(false),
+        )
+        .arg(
+            Arg::new("check-config")
+                .long("check-config")
+                .value_name("PATH")
+                .help("Check a config file for errors and exit (defaults to the user config)")
+                .num_args(0..=1)
+                .default_missing_value("")
+                .required(false),
+        )
         .arg(
             Arg::new("override-config")
                 .long("override-config")
                 .value_name("KEY_VALUE")
-                .help("Override a configuration value e.g. show_hidden=false")
-                .action(ArgAction::Set)
+                .help("Override a configuration value e.g. show_hidden=false (can be repeated)")
+                .action(ArgAction::Append)
+                .required(false),
+        )
+        .arg(
+            Arg::new("restore")
+                .long("restore")
+                .value_name("NAME")
+                .help("Restore a saved session (defaults to the one saved when quitting)")
+                .num_args(0..=1)
+                .default_missing_value(session::LAST_SESSION)
+                .conflicts_with("dir")
                 .required(false),
         )
         .arg(
@@ -114,14 +171,42 @@ async fn main() -> Result<()> {
         .get_matches();
 
     // Get useful system information.
-    let user = whoami::username();
-    let home_dir = dirs::home_dir().unwrap_or(format!("/home/{}", user).into());
-    let config_dir = dirs::config_dir().unwrap_or(home_dir.join(".config"));
-    let data_dir = dirs::data_dir().unwrap_or(home_dir.join(".local/share"));
-    let fm_config_dir = config_dir.join("fm");
-    let fm_data_dir = data_dir.join("fm");
-    let fm_config_file = fm_config_dir.join("config.toml");
+    let fm_config_dir = config::config_dir();
+    let fm_data_dir = config::data_dir();
+    let fm_config_file = match cmd.get_one::<String>("config") {
+        Some(path) if !Path::new(path).is_file() => {
+            return Err(anyhow!("config file does not exist: {}", path));
+        }
+        Some(path) => PathBuf::from(path),
+        None => fm_config_dir.join("config.toml"),
+    };
+
+    // Check the configuration file without starting the interface.
+    if let Some(path) = cmd.get_one::<String>("check-config") {
+        // Key bindings may refer to actions defined in another layer.
+        let actions: Vec<String> = config::load_config(&fm_config_file, &env::current_dir()?)
+            .map(|config| config.actions.into_keys().collect())
+            .unwrap_or_default();
+        let path = if path.is_empty() {
+            fm_config_file
+        } else {
+            PathBuf::from(path)
+        };
+        let diagnostics = check::check_config(&path, &actions)?;
+        for diagnostic in &diagnostics {
+            eprintln!("{}:{}", path.display(), diagnostic);
+        }
+        if !diagnostics.is_empty() {
+            process::exit(1);
+        }
+        println!("{}: ok", path.display());
+        return Ok(());
+    }
+
     let fm_log_file = fm_data_dir.join("log");
+    let fm_history_file = fm_data_dir.join("history");
+    let fm_views_file = fm_data_dir.join("views.toml");
+    let fm_sessions_dir = fm_data_dir.join("sessions");
 
     // Create program directories if they don't already exist.
     fs::create_dir_all(fm_config_dir)?;
@@ -153,10 +238,48 @@ async fn main() -> Result<()> {
         .target(env_logger::Target::Pipe(log_file))
         .init();
 
-    // Set current directory to the specified path.
-    if let Some(dir) = cmd.get_one::<String>("dir") {
-        env::set_current_dir(dir)?;
+    // A restored session opens its root instead of a directory.
+    let restore = cmd
+        .get_one::<String>("restore")
+        .map(|name| Session::load(&fm_sessions_dir, name))
+        .transpose()?;
+    let dir = match &restore {
+        Some(session) => Some(session.root.to_string_lossy().into_owned()),
+        None => cmd.get_one::<String>("dir").cloned(),
+    };
+
+    // Set current directory to the specified path, or connect to a remote one.
+    let mut remote = None;
+    if let Some(dir) = dir {
+        if dir.starts_with(sftp::SCHEME) {
+            remote = Some(vfs::resolve(Path::new(&dir))?);
+        } else {
+            env::set_current_dir(dir)?;
+        }
+    }
+
+    // Older versions copied their key table to the user configuration, it is removed once.
+    match config::migrate_keys(&fm_config_file) {
+        Ok(true) => info!(
+            "removed the unchanged key table of an older version from {}",
+            fm_config_file.display()
+        ),
+        Ok(false) => {}
+        Err(error) => warn!("could not migrate {}: {}", fm_config_file.display(), error),
+    }
+
+    // Load the configuration and apply any overrides before taking over the terminal.
+    let config_root = env::current_dir()?;
+    let mut configuration = config::load_config(&fm_config_file, &config_root)?;
+    let config_overrides: Vec<String> = cmd
+        .get_many::<String>("override-config")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    for expression in &config_overrides {
+        configuration.set(expression)?;
     }
+    let keymap = Keymap::new(&configuration.keys)?;
 
     // Get stdin and stdout handles and construct a Terminal object.
     let mut backend = CrosstermBackend::new(io::stdout());
@@ -168,27 +291,63 @@ async fn main() -> Result<()> {
         cursor::MoveTo(0, 0),
         EnterAlternateScreen,
         EnableMouseCapture,
+        EnableBracketedPaste,
         cursor::Hide
     )?;
     let mut terminal = Terminal::new(backend)?;
 
-    // Get current location and load the configuration.
-    let current_dir = env::current_dir()?;
-    let mut configuration = config::read_config(fm_config_file)?;
-    // Override configuration if specified.
-    if let Some(key_vals) = cmd.get_many::<String>("override-config") {
-        key_vals.for_each(|key_val| {
-            if let Some((key, value)) = key_val.split_once('=') {
-                configuration.set_key(key, value);
-            }
-        });
-    }
+    // Get current location.
+    let (vfs, current_dir) = match remote {
+        Some(remote) => remote,
+        None => (Arc::new(Local) as Arc<dyn Vfs>, env::current_dir()?),
+    };
     // Construct directory tree from current location.
-    let root = Application::read_dir(current_dir, configuration.show_hidden)?;
+    let root = Application::read_dir(
+        vfs.as_ref(),
+        current_dir,
+        configuration.show_hidden,
+        &configuration.sort,
+    )?;
 
     let (sender, receiver): (Sender<()>, Receiver<()>) = mpsc::channel();
-    let mut app = Application::new(&mut terminal, configuration, root, sender);
+    let mut app = Application::new(&mut terminal, vfs, configuration, root, sender);
+    app.command_bar.history = History::load(fm_history_file);
+    app.views = Views::load(fm_views_file, View::new(&app.configuration));
+    let root = app.files.path.clone();
+    app.restore_view(&root);
+    app.files = app.read_tree(root)?;
+    app.sessions_dir = fm_sessions_dir.clone();
+    app.last_dir_path = cmd.get_one::<String>("last-dir-path").cloned();
+    app.file_chooser = if let Some(path) = cmd.get_one::<String>("file-chooser-dir") {
+        Some(FileChooser::Dir(path.clone()))
+    } else if let Some(path) = cmd.get_one::<String>("file-chooser-single") {
+        Some(FileChooser::Single(path.clone()))
+    } else {
+        cmd.get_one::<String>("file-chooser-multiple")
+            .map(|path| FileChooser::Multiple(path.clone()))
+    };
+    app.keymap = keymap;
+    app.config_path = fm_config_file.clone();
+    app.config_root = config_root.clone();
+    app.config_overrides = config_overrides;
     app.set_title()?;
+    if let Some(path) = config::untrusted_config(&config_root) {
+        let message = format!(
+            "{} can't run commands until it is trusted with :trust",
+            path.display()
+        );
+        app.command_bar.message = Some(Message::Error(message));
+    }
+
+    // Restore the session asked for, or the last one if it was saved in this root.
+    let restore = restore.or_else(|| {
+        Session::load(&fm_sessions_dir, session::LAST_SESSION)
+            .ok()
+            .filter(|session| session.root == app.shared_path(&app.files.path))
+    });
+    if let Some(session) = restore {
+        app.restore_session(&session)?;
+    }
 
     let git_status = Arc::clone(&app.status.git_status);
     let commit_count = Arc::clone(&app.status.commit_count);
@@ -229,62 +388,109 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Reload the configuration when one of its files changes or on SIGHUP.
+    let reload = Arc::new(AtomicBool::new(false));
+    let layers = config::config_layers(&fm_config_file, &config_root);
+    let config_changed = Arc::clone(&reload);
+    task::spawn_blocking(move || {
+        let mut modified = modification_times(&layers);
+        loop {
+            sleep(CONFIG_POLL_INTERVAL);
+            let current = modification_times(&layers);
+            if current != modified {
+                modified = current;
+                config_changed.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    let mut hangup = signal(SignalKind::hangup())?;
+    let hangup_received = Arc::clone(&reload);
+    task::spawn(async move {
+        while hangup.recv().await.is_some() {
+            hangup_received.store(true, Ordering::Relaxed);
+        }
+    });
+
     app.updater.send(())?;
 
+    // Keys typed so far of a key sequence like `gg`.
+    let mut pending_keys: Vec<Key> = vec![];
+
     // Process all input and window events.
     loop {
+        app.hook_select();
         app.draw()?;
 
-        let event = read()?;
-
-        const NEW_DIR_CMD: &str = "new-dir ";
-        const NEW_FILE_CMD: &str = "new-file ";
-        const SEARCH_CMD: &str = "search ";
-        const SHELL_CMD: &str = "sh ";
+        let event = loop {
+            if reload.swap(false, Ordering::Relaxed) {
+                if let Err(error) = commands::execute(&mut app, "reload-config") {
+                    app.command_bar.message = Some(Message::Error(error.to_string()));
+                }
+                break None;
+            }
+            // Background jobs report their progress and when they finish.
+            if let Ok(report) = app.jobs.try_recv() {
+                app.command_bar.message = Some(report.message);
+                if report.refresh {
+                    app.refresh();
+                }
+                break None;
+            }
+            // Show the preview of the selected file once it is rendered.
+            if app.preview.take_ready() {
+                break None;
+            }
+            if poll(EVENT_POLL_INTERVAL)? {
+                break Some(read()?);
+            }
+            // Show new output of a captured command.
+            if app.output.as_ref().is_some_and(Output::is_running) {
+                break None;
+            }
+        };
+        let Some(event) = event else {
+            continue;
+        };
 
         if app.command_bar.command_entry_mode {
-            if let Event::Key(key) = event {
-                match key.code {
+            match event {
+                Event::Key(key) if app.command_bar.is_searching() => {
+                    app.command_bar.search_key(key);
+                }
+                Event::Key(key) => match key.code {
                     KeyCode::Esc => {
-                        app.command_bar.input_text = String::default();
-                        app.command_bar.prompt_text = ":".into();
-                        app.command_bar.command_entry_mode = false;
+                        app.command_bar.close();
                     }
-                    KeyCode::Backspace => {
-                        if app.command_bar.input_text == String::default() {
-                            app.command_bar.prompt_text = ":".into();
-                            app.command_bar.command_entry_mode = false;
-                        } else {
-                            app.command_bar.input_text.pop();
-                        }
+                    KeyCode::Backspace if app.command_bar.input_text.is_empty() => {
+                        app.command_bar.close();
                     }
                     KeyCode::Enter => {
-                        if app.command_bar.input_text.starts_with(NEW_DIR_CMD) {
-                            app.new_dir(app.command_bar.command_parameters(NEW_DIR_CMD));
-                        } else if app.command_bar.input_text.starts_with(NEW_FILE_CMD) {
-                            app.new_file(app.command_bar.command_parameters(NEW_FILE_CMD));
-                        } else if app.command_bar.input_text.starts_with(SEARCH_CMD) {
-                            app.search(app.command_bar.command_parameters(SEARCH_CMD));
-                        } else if app.command_bar.input_text.starts_with(SHELL_CMD) {
-                            app.cmd_shell(app.command_bar.command_parameters(SHELL_CMD));
-                        } else if app.command_bar.input_text == "path" {
-                            app.cmd_path();
-                        } else if app.command_bar.input_text == "mv" {
-                            app.cmd_mv();
-                        } else if app.command_bar.input_text == "cp" {
-                            app.cmd_cp();
+                        let input = app.command_bar.input_text.clone();
+                        app.command_bar.record();
+                        app.command_bar.close();
+                        if let Err(error) = commands::execute(&mut app, &input) {
+                            app.command_bar.message = Some(Message::Error(error.to_string()));
                         }
-                        app.command_bar.input_text = String::default();
-                        app.command_bar.prompt_text = ":".into();
-                        app.command_bar.command_entry_mode = false;
                     }
-                    KeyCode::Char(c) => {
-                        app.command_bar.input_text.push(c);
+                    KeyCode::Tab => {
+                        app.complete();
+                    }
+                    KeyCode::BackTab => {
+                        app.command_bar.complete_previous();
+                    }
+                    _ => {
+                        app.command_bar.edit(key);
                     }
-                    _ => {}
+                },
+                Event::Paste(content) => {
+                    app.command_bar.paste(&content);
                 }
+                _ => {}
             }
         } else {
+            if let Event::Key(_) = event {
+                app.command_bar.message = None;
+            }
             match event {
                 Event::Resize(_, _) => {
                     let (original_size, new_size) = flush_resize_events(event.clone());
@@ -316,14 +522,15 @@ async fn main() -> Result<()> {
                             let clicked = (row - 1) as usize + offset;
                             app.list_state.select(Some(clicked));
                             if let Some(selected) = app.selected() {
-                                if selected.metadata.is_dir() {
+                                if selected.is_dir() {
                                     if app.expanded.contains(&selected.path) {
                                         app.collapse()
                                     } else {
                                         app.expand();
                                     }
-                                } else {
-                                    app.open();
+                                } else if let Err(error) = app.open() {
+                                    app.command_bar.message =
+                                        Some(Message::Error(error.to_string()));
                                 }
                             }
                         }
@@ -353,167 +560,32 @@ async fn main() -> Result<()> {
                     MouseEventKind::ScrollLeft => {}
                     MouseEventKind::ScrollRight => {}
                 },
-                Event::Key(KeyEvent {
-                    code, modifiers, ..
-                }) => match (code, modifiers) {
-                    (KeyCode::Char(':'), KeyModifiers::NONE) => {
-                        app.command_bar.command_entry_mode = true;
-                    }
-                    (KeyCode::Esc, KeyModifiers::NONE) => {
-                        app.clear();
-                    }
-                    (KeyCode::Char(';'), KeyModifiers::NONE) => {
-                        app.change_root()?;
-                    }
-                    (KeyCode::Char('j'), KeyModifiers::NONE) => {
-                        app.previous_root()?;
-                    }
-                    (KeyCode::Char('q'), KeyModifiers::NONE) => {
-                        app.quit()?;
-                    }
-                    (KeyCode::Char('Q'), KeyModifiers::SHIFT) => {
-                        app.quit_change(cmd.get_one::<String>("last-dir-path"))?;
-                    }
-                    (KeyCode::Char('h'), KeyModifiers::NONE) => {
-                        if let Some(output_path) = cmd.get_one::<String>("file-chooser-dir") {
-                            app.quit_print_dir(output_path.clone())?;
-                        } else if let Some(output_path) =
-                            cmd.get_one::<String>("file-chooser-single")
-                        {
-                            app.quit_print_file(output_path.clone())?;
-                        } else if let Some(output_path) =
-                            cmd.get_one::<String>("file-chooser-multiple")
-                        {
-                            app.quit_print_marked(output_path.clone())?;
-                        }
-                    }
-                    (KeyCode::Down, KeyModifiers::NONE) => {
-                        app.down();
-                    }
-                    (KeyCode::Char('k'), KeyModifiers::NONE) => {
-                        app.down();
-                    }
-                    (KeyCode::Up, KeyModifiers::NONE) => {
-                        app.up();
-                    }
-                    (KeyCode::Char('l'), KeyModifiers::NONE) => {
-                        app.up();
-                    }
-                    (KeyCode::Char('x'), KeyModifiers::NONE) => {
-                        app.expand_toggle();
-                    }
-                    (KeyCode::Left, KeyModifiers::NONE) => {
-                        app.collapse();
-                    }
-                    (KeyCode::Right, KeyModifiers::NONE) => {
-                        app.expand();
-                    }
-                    (KeyCode::Char(' '), KeyModifiers::NONE) => {
-                        app.mark();
-                    }
-                    (KeyCode::Char('F'), KeyModifiers::SHIFT) => {
-                        app.file_manager();
-                    }
-                    (KeyCode::Char('E'), KeyModifiers::SHIFT) => {
-                        app.editx();
-                    }
-                    (KeyCode::Char('e'), KeyModifiers::NONE) => {
-                        app.edit();
-                    }
-                    (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
-                        app.editnox();
-                    }
-                    (KeyCode::Char('S'), KeyModifiers::SHIFT) => {
-                        app.shellx();
-                    }
-                    (KeyCode::Char('s'), KeyModifiers::NONE) => {
-                        app.shell();
+                Event::Key(key) if app.open_with.is_some() => {
+                    if let Err(error) = app.open_with_key(key) {
+                        app.command_bar.message = Some(Message::Error(error.to_string()));
                     }
-                    (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-                        app.shellx_root();
-                    }
-                    (KeyCode::Char('i'), KeyModifiers::NONE) => {
-                        app.preview();
-                    }
-                    (KeyCode::Char('o'), KeyModifiers::NONE) => {
-                        app.open();
-                    }
-                    (KeyCode::Char('r'), KeyModifiers::NONE) => {
-                        app.rename();
-                    }
-                    (KeyCode::Char('V'), KeyModifiers::SHIFT) => {
-                        app.vscode();
-                    }
-                    (KeyCode::Char('v'), KeyModifiers::CONTROL) => {
-                        app.vscode_root();
-                    }
-                    (KeyCode::Char('T'), KeyModifiers::SHIFT) => {
-                        app.trash();
-                    }
-                    (KeyCode::Char('I'), KeyModifiers::SHIFT) => {
-                        app.images();
-                    }
-                    (KeyCode::Char('/'), KeyModifiers::NONE) => {
-                        app.command_bar.input_text = SEARCH_CMD.into();
-                        app.command_bar.command_entry_mode = true;
-                    }
-                    (KeyCode::Char('?'), KeyModifiers::NONE) => {
-                        app.search_all().expect("search all failed");
-                    }
-                    (KeyCode::Char('D'), KeyModifiers::SHIFT) => {
-                        app.drag_and_drop();
-                    }
-                    (KeyCode::Char('L'), KeyModifiers::SHIFT) => {
-                        app.git_log();
-                    }
-                    (KeyCode::Char('N'), KeyModifiers::SHIFT) => {
-                        app.command_bar.input_text = NEW_DIR_CMD.into();
-                        app.command_bar.command_entry_mode = true;
-                    }
-                    (KeyCode::Char('n'), KeyModifiers::NONE) => {
-                        app.command_bar.input_text = NEW_FILE_CMD.into();
-                        app.command_bar.command_entry_mode = true;
-                    }
-                    (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
-                        app.refresh();
-                    }
-                    (KeyCode::Char('y'), KeyModifiers::NONE) => {
-                        app.copy();
-                    }
-                    (KeyCode::Char('c'), KeyModifiers::NONE) => {
-                        app.cut();
-                    }
-                    (KeyCode::Char('Z'), KeyModifiers::SHIFT) => {
-                        app.toggle_hidden();
-                    }
-                    (KeyCode::Char('C'), KeyModifiers::SHIFT) => {
-                        app.clear_files();
-                    }
-                    (KeyCode::Char('p'), KeyModifiers::NONE) => {
-                        app.paste(false);
-                    }
-                    (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
-                        app.paste(true);
+                }
+                Event::Key(key) if app.confirmation.is_some() => {
+                    if let Err(error) = app.confirm(key.code == KeyCode::Char('y')) {
+                        app.command_bar.message = Some(Message::Error(error.to_string()));
                     }
-                    (KeyCode::Char('g'), KeyModifiers::NONE) => match read()? {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('g'),
-                            ..
-                        }) => {
-                            app.top();
+                }
+                Event::Key(key) => {
+                    pending_keys.push(Key::from(key));
+                    match app.keymap.get(&pending_keys) {
+                        Some(Binding::Sequence(_)) => {}
+                        Some(Binding::Action(action)) => {
+                            let action = action.clone();
+                            pending_keys.clear();
+                            if let Err(error) = commands::run_action(&mut app, &action) {
+                                app.command_bar.message = Some(Message::Error(error.to_string()));
+                            }
                         }
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('e'),
-                            ..
-                        }) => {
-                            app.bottom();
+                        None => {
+                            pending_keys.clear();
                         }
-                        _ => {}
-                    },
-                    _ => {
-                        dbgf!(format!("Unknown event: {:?} {:?}", code, modifiers));
                     }
-                },
+                }
                 _ => {}
             }
         }
@@ -537,6 +609,14 @@ fn flush_resize_events(event: Event) -> ((u16, u16), (u16, u16)) {
     ((0, 0), (0, 0))
 }
 
+fn modification_times(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths
+        .iter()
+        .map(|path| fs::metadata(path).and_then(|metadata| metadata.modified()))
+        .map(Result::ok)
+        .collect()
+}
+
 fn spawn_server() -> Result<()> {
     let _process = subprocess::Popen::create(
         &["fm-server"],
@@ -0,0 +1,298 @@
+use std::io::Read;
+use std::path::Path;
+use std::{env, fs};
+
+use crate::config::{Config, Opener};
+
+/// Number of bytes read from the start of a file to detect its type.
+const SNIFF_SIZE: usize = 512;
+
+/// Magic bytes at an offset from the start of a file and the mime type they identify.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"BM", "image/bmp"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x7fELF", "application/x-executable"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    (4, b"ftyp", "video/mp4"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"#!", "text/x-shellscript"),
+];
+
+/// Mime types of common extensions, anything else is detected from the contents.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("avi", "video/x-msvideo"),
+    ("bmp", "image/bmp"),
+    ("bz2", "application/x-bzip2"),
+    ("c", "text/x-c"),
+    ("cpp", "text/x-c++"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("epub", "application/epub+zip"),
+    ("flac", "audio/flac"),
+    ("gif", "image/gif"),
+    ("go", "text/x-go"),
+    ("gz", "application/gzip"),
+    ("h", "text/x-c"),
+    ("hpp", "text/x-c++"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("java", "text/x-java"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("lua", "text/x-lua"),
+    ("md", "text/markdown"),
+    ("mkv", "video/x-matroska"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ogg", "audio/ogg"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("py", "text/x-python"),
+    ("rb", "text/x-ruby"),
+    ("rs", "text/x-rust"),
+    ("sh", "text/x-shellscript"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("toml", "application/toml"),
+    ("ts", "text/x-typescript"),
+    ("txt", "text/plain"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webp", "image/webp"),
+    ("xml", "application/xml"),
+    ("xz", "application/x-xz"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+    ("zst", "application/zstd"),
+];
+
+/// Text formats whose mime type doesn't start with `text/`.
+const TEXT_APPLICATIONS: &[&str] = &[
+    "application/json",
+    "application/toml",
+    "application/xml",
+    "application/yaml",
+];
+
+/// Returns the openers of a file: the matching `[[openers]]` in the order they are listed,
+/// then `$VISUAL` or `$EDITOR` for text files and finally `xdg-open`.
+pub fn openers(config: &Config, path: &Path) -> Vec<Opener> {
+    let mime = mime_type(path);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let mut openers: Vec<Opener> = config
+        .openers
+        .iter()
+        .filter(|opener| matches(opener, &name, &mime))
+        .cloned()
+        .collect();
+    if is_text(&mime) {
+        openers.push(editor());
+    }
+    openers.push(Opener {
+        name: "xdg-open".to_owned(),
+        command: "xdg-open".to_owned(),
+        ..Opener::default()
+    });
+    openers
+}
+
+/// Returns the text editor from `$VISUAL` or `$EDITOR`, `vi` when neither is set.
+pub fn editor() -> Opener {
+    let command = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_owned());
+    Opener {
+        name: command.clone(),
+        command,
+        terminal: true,
+        ..Opener::default()
+    }
+}
+
+/// Returns the mime type of a file from its extension, or from its first bytes when the
+/// extension is missing or unknown.
+pub fn mime_type(path: &Path) -> String {
+    if path.is_dir() {
+        return "inode/directory".to_owned();
+    }
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let known = extension.and_then(|extension| {
+        EXTENSIONS
+            .iter()
+            .find(|(name, _)| *name == extension)
+            .map(|(_, mime)| *mime)
+    });
+    if let Some(mime) = known {
+        return mime.to_owned();
+    }
+    let mut header = Vec::with_capacity(SNIFF_SIZE);
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(SNIFF_SIZE as u64).read_to_end(&mut header);
+    }
+    sniff(&header).to_owned()
+}
+
+/// Detects the mime type of the start of a file from magic bytes, falling back to
+/// `text/plain` for UTF-8 without NUL bytes.
+pub fn sniff(header: &[u8]) -> &'static str {
+    let magic = MAGIC.iter().find(|(offset, magic, _)| {
+        header
+            .get(*offset..offset + magic.len())
+            .is_some_and(|bytes| bytes == *magic)
+    });
+    if let Some((_, _, mime)) = magic {
+        return mime;
+    }
+    if header.starts_with(b"RIFF") {
+        match header.get(8..12) {
+            Some(b"WEBP") => return "image/webp",
+            Some(b"WAVE") => return "audio/wav",
+            Some(b"AVI ") => return "video/x-msvideo",
+            _ => {}
+        }
+    }
+    // The header may end in the middle of a character.
+    let utf8 = match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(error) => error.error_len().is_none(),
+    };
+    if utf8 && !header.contains(&0) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// Returns whether files of the mime type can be opened in a text editor.
+pub fn is_text(mime: &str) -> bool {
+    mime.starts_with("text/") || TEXT_APPLICATIONS.contains(&mime)
+}
+
+/// Returns whether an opener handles a file with the given name and mime type.
+pub fn matches(opener: &Opener, name: &str, mime: &str) -> bool {
+    opener
+        .mime
+        .iter()
+        .any(|pattern| mime_matches(pattern, mime))
+        || opener
+            .globs
+            .iter()
+            .any(|pattern| glob_matches(pattern, name))
+}
+
+/// Matches a mime type against an exact type, a `type/*` pattern, `*/*` or `*`.
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(kind) => kind == "*" || mime.split('/').next() == Some(kind),
+        None => pattern == "*" || pattern == mime,
+    }
+}
+
+/// Matches a file name against a pattern where `*` is any text and `?` any character.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last `*` and the name position it is matched up to.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after, matched)) => {
+                    p = after;
+                    n = matched + 1;
+                    star = Some((after, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(sniff(b"#!/bin/sh\necho hi\n"), "text/x-shellscript");
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff("plain text é".as_bytes()), "text/plain");
+        assert_eq!(sniff(&"é".as_bytes()[..1]), "text/plain");
+        assert_eq!(sniff(b"\x00\x01\x02"), "application/octet-stream");
+        let mut tar = vec![0; 300];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff(&tar), "application/x-tar");
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.rs", "main.rs"));
+        assert!(!glob_matches("*.rs", "main.rsx"));
+        assert!(glob_matches("Makefile", "Makefile"));
+        assert!(glob_matches("*.tar.*", "a.tar.gz"));
+        assert!(glob_matches("file?.txt", "file1.txt"));
+        assert!(!glob_matches("file?.txt", "file.txt"));
+        assert!(glob_matches("*a*b", "xaayb"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn test_matches() {
+        let opener = Opener {
+            mime: vec!["image/*".into()],
+            globs: vec!["*.kra".into()],
+            ..Opener::default()
+        };
+        assert!(matches(&opener, "a.png", "image/png"));
+        assert!(matches(&opener, "a.kra", "application/zip"));
+        assert!(!matches(&opener, "a.txt", "text/plain"));
+        assert!(is_text("text/x-rust"));
+        assert!(is_text("application/json"));
+        assert!(!is_text("application/pdf"));
+    }
+}
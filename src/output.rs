@@ -0,0 +1,292 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{self, ExitStatus, Stdio};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, Result};
+
+use crate::command_bar::Message;
+
+/// Sent by background jobs to report their progress and when they finish.
+pub struct Report {
+    pub message: Message,
+    /// Whether the file tree should be refreshed.
+    pub refresh: bool,
+}
+
+/// Number of lines of output kept, older lines are dropped.
+const MAX_LINES: usize = 10_000;
+
+/// Minimum time between two progress reports of a background job.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reports the progress of a background job in the status bar, at most every
+/// `PROGRESS_INTERVAL` so the interface isn't redrawn for every file.
+pub struct Progress<'a> {
+    title: &'a str,
+    sender: &'a Sender<Report>,
+    last: Option<Instant>,
+}
+
+impl<'a> Progress<'a> {
+    pub fn new(title: &'a str, sender: &'a Sender<Report>) -> Self {
+        Progress {
+            title,
+            sender,
+            last: None,
+        }
+    }
+
+    pub fn report(&mut self, progress: &str) {
+        if self
+            .last
+            .is_some_and(|last| last.elapsed() < PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last = Some(Instant::now());
+        let _ = self.sender.send(Report {
+            message: Message::Info(format!("{}: {}", self.title, progress)),
+            refresh: false,
+        });
+    }
+}
+
+/// Output of a captured shell command shown in a pane below the file tree until dismissed.
+pub struct Output {
+    pub command: String,
+    /// The last `MAX_LINES` lines without escape sequences.
+    lines: Arc<Mutex<VecDeque<String>>>,
+    status: Arc<Mutex<Option<ExitStatus>>>,
+    /// First visible line, the pane follows the end of the output until it is scrolled.
+    scroll: Option<usize>,
+}
+
+impl Output {
+    /// Runs the command with stdout and stderr collected line by line in the background.
+    /// A message is sent when the command exits.
+    pub fn spawn(
+        mut command: process::Command,
+        input: &str,
+        refresh: bool,
+        sender: Sender<Report>,
+    ) -> Result<Self, Error> {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let output = Output {
+            command: input.to_owned(),
+            lines: Arc::default(),
+            status: Arc::new(Mutex::new(None)),
+            scroll: None,
+        };
+        let mut readers = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(output.read_lines(stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(output.read_lines(stderr));
+        }
+        let status = Arc::clone(&output.status);
+        let input = input.to_owned();
+        thread::spawn(move || {
+            for reader in readers {
+                let _ = reader.join();
+            }
+            let message = match child.wait() {
+                Ok(exit_status) => {
+                    if let Ok(mut status) = status.lock() {
+                        *status = Some(exit_status);
+                    }
+                    exit_message(&input, exit_status)
+                }
+                Err(error) => Message::Error(format!("{}: {}", input, error)),
+            };
+            let _ = sender.send(Report { message, refresh });
+        });
+        Ok(output)
+    }
+
+    fn read_lines<R: Read + Send + 'static>(&self, reader: R) -> thread::JoinHandle<()> {
+        let lines = Arc::clone(&self.lines);
+        thread::spawn(move || {
+            for line in BufReader::new(reader).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if let Ok(mut lines) = lines.lock() {
+                    lines.push_back(strip_control(&line));
+                    if lines.len() > MAX_LINES {
+                        lines.pop_front();
+                    }
+                }
+            }
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status.lock().is_ok_and(|status| status.is_none())
+    }
+
+    /// Returns the title of the pane with the command and its state.
+    pub fn title(&self) -> String {
+        match self.status.lock().ok().and_then(|status| *status) {
+            None => format!(" {} (running) ", self.command),
+            Some(status) if status.success() => format!(" {} ", self.command),
+            Some(status) => format!(" {} ({}) ", self.command, status),
+        }
+    }
+
+    /// Returns the lines visible in a pane of the given height.
+    pub fn visible_lines(&self, height: usize) -> Vec<String> {
+        let Ok(lines) = self.lines.lock() else {
+            return vec![];
+        };
+        let top = top_line(lines.len(), height, self.scroll);
+        lines.iter().skip(top).take(height).cloned().collect()
+    }
+
+    pub fn scroll_up(&mut self, height: usize) {
+        let top = top_line(self.line_count(), height, self.scroll);
+        self.scroll = Some(top.saturating_sub(height));
+    }
+
+    /// Scrolls down a page and starts following the output again once the end is reached.
+    pub fn scroll_down(&mut self, height: usize) {
+        let count = self.line_count();
+        let top = top_line(count, height, self.scroll) + height;
+        self.scroll = (top < count.saturating_sub(height)).then_some(top);
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.lock().map_or(0, |lines| lines.len())
+    }
+}
+
+/// Returns the message shown in the status bar when a shell command exits.
+pub fn exit_message(input: &str, status: ExitStatus) -> Message {
+    if status.success() {
+        Message::Info(format!("{}: done", input))
+    } else {
+        Message::Error(format!("{}: {}", input, status))
+    }
+}
+
+/// Removes escape sequences like colours and other control characters, which would corrupt the
+/// interface. Only the text after the last carriage return is kept, as in a terminal, and tabs
+/// are expanded.
+fn strip_control(line: &str) -> String {
+    let line = line
+        .rsplit('\r')
+        .find(|part| !part.is_empty())
+        .unwrap_or("");
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                // Control sequences end with a character from `@` to `~`.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Operating system commands like window titles end with BEL or `ESC \`.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\t' => stripped.push_str("    "),
+            c if c.is_control() => {}
+            c => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+fn top_line(count: usize, height: usize, scroll: Option<usize>) -> usize {
+    let end = count.saturating_sub(height);
+    scroll.map_or(end, |scroll| scroll.min(end))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn test_output() {
+        let mut command = process::Command::new("sh");
+        command.args(["-c", "echo one; echo two >&2; exit 3"]);
+        let (sender, receiver) = mpsc::channel();
+        let output = Output::spawn(command, "test", true, sender).unwrap();
+        let report = receiver.recv().unwrap();
+        assert_eq!(
+            report.message,
+            Message::Error("test: exit status: 3".into())
+        );
+        assert!(!output.is_running());
+        let mut lines = output.visible_lines(10);
+        lines.sort();
+        assert_eq!(lines, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_strip_control() {
+        assert_eq!(strip_control("\x1b[1;31merror\x1b[0m: x"), "error: x");
+        assert_eq!(strip_control("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_control("\x1b]8;;url\x1b\\link"), "link");
+        assert_eq!(strip_control("10%\r50%\r100%\r"), "100%");
+        assert_eq!(strip_control("a\tb\x08\x00c"), "a    bc");
+    }
+
+    #[test]
+    fn test_progress() {
+        let (sender, receiver) = mpsc::channel();
+        let mut progress = Progress::new("extract a.zip", &sender);
+        progress.report("10 files");
+        progress.report("20 files");
+        let report = receiver.try_recv().unwrap();
+        assert_eq!(
+            report.message,
+            Message::Info("extract a.zip: 10 files".into())
+        );
+        assert!(!report.refresh);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut output = Output {
+            command: "test".into(),
+            lines: Arc::new(Mutex::new((0..10).map(|n| n.to_string()).collect())),
+            status: Arc::new(Mutex::new(None)),
+            scroll: None,
+        };
+        assert_eq!(output.visible_lines(4), vec!["6", "7", "8", "9"]);
+        output.scroll_up(4);
+        assert_eq!(output.visible_lines(4), vec!["2", "3", "4", "5"]);
+        output.scroll_up(4);
+        output.scroll_up(4);
+        assert_eq!(output.visible_lines(4), vec!["0", "1", "2", "3"]);
+        output.scroll_down(4);
+        assert_eq!(output.visible_lines(4), vec!["4", "5", "6", "7"]);
+        output.scroll_down(4);
+        assert_eq!(output.scroll, None);
+        output.lines.lock().unwrap().push_back("10".into());
+        assert_eq!(output.visible_lines(4), vec!["7", "8", "9", "10"]);
+    }
+}
@@ -0,0 +1,657 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+
+use crate::vfs::Vfs;
+use crate::{archive, highlight, opener};
+
+/// Maximum number of bytes read from a file for its preview.
+const MAX_BYTES: u64 = 64 * 1024;
+
+/// Number of bytes in a row of a hex dump.
+const HEX_ROW: usize = 16;
+
+/// Descriptions of the file types detected from magic bytes shown above a hex dump.
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("application/gzip", "gzip compressed data"),
+    ("application/pdf", "PDF document"),
+    ("application/vnd.sqlite3", "SQLite database"),
+    ("application/x-7z-compressed", "7-zip archive"),
+    ("application/x-bzip2", "bzip2 compressed data"),
+    ("application/x-executable", "ELF executable"),
+    ("application/x-tar", "tar archive"),
+    ("application/x-xz", "XZ compressed data"),
+    ("application/zip", "Zip archive"),
+    ("application/zstd", "Zstandard compressed data"),
+    ("audio/flac", "FLAC audio"),
+    ("audio/mpeg", "MP3 audio"),
+    ("audio/ogg", "Ogg audio"),
+    ("audio/wav", "WAVE audio"),
+    ("image/bmp", "BMP image"),
+    ("image/gif", "GIF image"),
+    ("image/jpeg", "JPEG image"),
+    ("image/png", "PNG image"),
+    ("image/webp", "WebP image"),
+    ("video/mp4", "MP4 video"),
+    ("video/x-matroska", "Matroska video"),
+    ("video/x-msvideo", "AVI video"),
+];
+
+/// Preview of the selected file shown in a pane beside the tree, rendered in the background
+/// by a single worker thread.
+pub struct Preview {
+    vfs: Arc<dyn Vfs>,
+    path: Option<PathBuf>,
+    content: Arc<Mutex<Content>>,
+    /// First visible line, the preview scrolls independently of the tree.
+    scroll: usize,
+    /// Offset of the first row of a hex dump, which is read from the file for each page.
+    offset: u64,
+    max_lines: usize,
+    theme: String,
+    /// Incremented for every new preview so a slow preview of a previous file is dropped.
+    generation: Arc<AtomicUsize>,
+    /// Set when a preview is rendered and the screen should be redrawn.
+    ready: Arc<AtomicBool>,
+    requests: Sender<Request>,
+}
+
+/// A preview for the worker thread to render.
+struct Request {
+    vfs: Arc<dyn Vfs>,
+    path: PathBuf,
+    max_lines: usize,
+    theme: String,
+    offset: u64,
+    generation: usize,
+}
+
+/// Rendered lines of a preview.
+#[derive(Default)]
+pub struct Content {
+    pub lines: Vec<Spans<'static>>,
+    /// Size of a binary file shown as a hex dump.
+    pub hex_size: Option<u64>,
+}
+
+impl Preview {
+    pub fn new(vfs: Arc<dyn Vfs>) -> Self {
+        let content = Arc::default();
+        let generation = Arc::default();
+        let ready = Arc::default();
+        let (requests, receiver) = mpsc::channel();
+        let worker = (
+            Arc::clone(&content),
+            Arc::clone(&generation),
+            Arc::clone(&ready),
+        );
+        thread::spawn(move || work(receiver, worker.0, worker.1, worker.2));
+        Preview {
+            vfs,
+            path: None,
+            content,
+            scroll: 0,
+            offset: 0,
+            max_lines: 0,
+            theme: String::new(),
+            generation,
+            ready,
+            requests,
+        }
+    }
+
+    /// Starts rendering the preview of a file unless it is already shown.
+    pub fn update(&mut self, path: Option<&Path>, max_lines: usize, theme: &str) {
+        if self.path.as_deref() == path {
+            return;
+        }
+        self.path = path.map(Path::to_path_buf);
+        self.scroll = 0;
+        self.offset = 0;
+        self.max_lines = max_lines;
+        self.theme = theme.to_owned();
+        if let Ok(mut content) = self.content.lock() {
+            *content = Content::default();
+        }
+        self.render();
+    }
+
+    /// Renders the preview in the background, the current content stays visible until then.
+    fn render(&mut self) {
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let _ = self.requests.send(Request {
+            vfs: Arc::clone(&self.vfs),
+            path,
+            max_lines: self.max_lines,
+            theme: self.theme.clone(),
+            offset: self.offset,
+            generation,
+        });
+    }
+
+    /// Renders the preview again on the next update e.g. after the file changed.
+    pub fn invalidate(&mut self) {
+        self.path = None;
+    }
+
+    /// Returns whether a preview was rendered since the last call.
+    pub fn take_ready(&self) -> bool {
+        self.ready.swap(false, Ordering::Relaxed)
+    }
+
+    pub fn visible_lines(&self, height: usize) -> Vec<Spans<'static>> {
+        self.content
+            .lock()
+            .map(|content| {
+                content
+                    .lines
+                    .iter()
+                    .skip(self.scroll)
+                    .take(height)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Scrolls up a page, a hex dump reads the previous page from the file.
+    pub fn scroll_up(&mut self, height: usize) {
+        if self.hex_size().is_some() {
+            let step = (hex_rows(height) * HEX_ROW) as u64;
+            if self.offset > 0 {
+                self.offset = self.offset.saturating_sub(step);
+                self.render();
+            }
+            return;
+        }
+        self.scroll = self.scroll.saturating_sub(height);
+    }
+
+    /// Scrolls down a page, a hex dump reads the next page from the file.
+    pub fn scroll_down(&mut self, height: usize) {
+        if let Some(size) = self.hex_size() {
+            let rows = hex_rows(height);
+            let offset = (self.offset + (rows * HEX_ROW) as u64).min(last_page(size, rows));
+            if offset != self.offset {
+                self.offset = offset;
+                self.render();
+            }
+            return;
+        }
+        let count = self.content.lock().map_or(0, |content| content.lines.len());
+        self.scroll = (self.scroll + height).min(count.saturating_sub(height));
+    }
+
+    fn hex_size(&self) -> Option<u64> {
+        self.content
+            .lock()
+            .ok()
+            .and_then(|content| content.hex_size)
+    }
+}
+
+/// Renders previews until the preview is dropped. Requests which were replaced by a newer one
+/// while a preview was rendered are skipped.
+fn work(
+    requests: Receiver<Request>,
+    content: Arc<Mutex<Content>>,
+    current: Arc<AtomicUsize>,
+    ready: Arc<AtomicBool>,
+) {
+    while let Ok(mut request) = requests.recv() {
+        while let Ok(newer) = requests.try_recv() {
+            request = newer;
+        }
+        if current.load(Ordering::Relaxed) != request.generation {
+            continue;
+        }
+        let rendered = render(
+            request.vfs.as_ref(),
+            &request.path,
+            request.max_lines,
+            &request.theme,
+            request.offset,
+        );
+        if let Ok(mut content) = content.lock() {
+            if current.load(Ordering::Relaxed) == request.generation {
+                *content = rendered;
+                ready.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Returns the number of rows of a hex dump visible below its header.
+fn hex_rows(height: usize) -> usize {
+    height.saturating_sub(1).max(1)
+}
+
+/// Returns the offset of the last page of a hex dump so that the end of the file fills it.
+fn last_page(size: u64, rows: usize) -> u64 {
+    let row = HEX_ROW as u64;
+    let total = size.div_ceil(row);
+    total.saturating_sub(rows as u64) * row
+}
+
+/// Renders up to `max_lines` lines previewing a file: the start of a text file highlighted
+/// with the theme, the children of a directory, a hex dump of a binary file from the offset,
+/// the target of a symlink and files inside an archive. Only regular files are read, the type
+/// of any other file is shown since reading e.g. a named pipe could block.
+pub fn render(vfs: &dyn Vfs, path: &Path, max_lines: usize, theme: &str, offset: u64) -> Content {
+    if let Some((archive, name)) = archive::split(path).filter(|_| vfs.url(path).is_none()) {
+        return member(&archive, &name, max_lines, theme);
+    }
+    let mut content = Content::default();
+    let link = vfs.read_link(path).ok();
+    if let Some(target) = &link {
+        content
+            .lines
+            .push(Spans::from(format!("-> {}", target.to_string_lossy())));
+    }
+    let stat = match vfs.stat(path) {
+        Ok(stat) => stat,
+        Err(error) if error.kind() == io::ErrorKind::NotFound && link.is_some() => {
+            content.lines.push(Spans::from("broken link"));
+            return content;
+        }
+        Err(error) => return Content::from(error.to_string()),
+    };
+    if link.is_some() {
+        content.lines.push(Spans::default());
+    }
+    if stat.is_dir {
+        let lines = directory(vfs, path, max_lines).into_iter().map(Spans::from);
+        content.lines.extend(lines);
+    } else if stat.is_file {
+        let file = file(vfs, path, stat.len, max_lines, theme, offset);
+        content.lines.extend(file.lines);
+        content.hex_size = file.hex_size;
+    } else {
+        content.lines.push(Spans::from(file_type(stat.mode)));
+    }
+    content.lines.truncate(max_lines);
+    content
+}
+
+impl From<String> for Content {
+    fn from(line: String) -> Self {
+        Content {
+            lines: vec![Spans::from(line)],
+            hex_size: None,
+        }
+    }
+}
+
+/// Describes a file which is neither a regular file nor a directory from its mode.
+fn file_type(mode: u32) -> &'static str {
+    match mode & 0o170000 {
+        0o010000 => "named pipe",
+        0o020000 => "character device",
+        0o060000 => "block device",
+        0o140000 => "socket",
+        _ => "special file",
+    }
+}
+
+/// Lists the children of a directory, directories first. Only the entries which fit are read
+/// so they are the first ones in the order of the directory.
+fn directory(vfs: &dyn Vfs, path: &Path, max_lines: usize) -> Vec<String> {
+    let entries = match vfs.list_limit(path, max_lines + 1) {
+        Ok(entries) => entries,
+        Err(error) => return vec![error.to_string()],
+    };
+    let complete = entries.len() <= max_lines;
+    let children = entries
+        .into_iter()
+        .map(|entry| {
+            let name = entry.file_name().unwrap_or_default();
+            let is_dir = vfs.stat(&entry).is_ok_and(|stat| stat.is_dir);
+            (!is_dir, name.to_string_lossy().into_owned())
+        })
+        .collect();
+    listing(children, max_lines, complete)
+}
+
+/// Lists names with a `/` after directories, which come first. The number of names which
+/// don't fit is only shown when `complete` tells that all children are listed.
+fn listing(mut children: Vec<(bool, String)>, max_lines: usize, complete: bool) -> Vec<String> {
+    if children.is_empty() {
+        return vec!["empty directory".to_owned()];
+    }
+    children.sort();
+    let count = children.len();
+    let mut lines: Vec<String> = children
+        .into_iter()
+        .take(max_lines)
+        .map(|(file, name)| if file { name } else { format!("{}/", name) })
+        .collect();
+    if count > max_lines && max_lines > 0 {
+        lines.pop();
+        if complete {
+            lines.push(format!("... {} more", count - max_lines + 1));
+        } else {
+            lines.push("... more".to_owned());
+        }
+    }
+    lines
+}
+
+/// Shows the first lines of a text file with line numbers or a hex dump of a binary file.
+fn file(
+    vfs: &dyn Vfs,
+    path: &Path,
+    size: u64,
+    max_lines: usize,
+    theme: &str,
+    offset: u64,
+) -> Content {
+    let header = match vfs.read(path, 0, MAX_BYTES) {
+        Ok(header) => header,
+        Err(error) => return Content::from(error.to_string()),
+    };
+    let mime = opener::sniff(&header);
+    if opener::is_text(mime) {
+        return text(path, &header, max_lines, theme);
+    }
+    let mut lines = vec![summary(mime, size)];
+    match hex_dump(vfs, path, offset, max_lines.saturating_sub(1)) {
+        Ok(rows) => lines.extend(rows),
+        Err(error) => lines.push(Spans::from(error.to_string())),
+    }
+    Content {
+        lines,
+        hex_size: Some(size),
+    }
+}
+
+/// Previews a file or directory inside an archive. Binary files show a hex dump of their
+/// start which scrolls like text since members can't be read from an offset.
+fn member(archive: &Path, name: &Path, max_lines: usize, theme: &str) -> Content {
+    let members = match archive::cached(archive) {
+        Ok(members) => members,
+        Err(error) => return Content::from(error.to_string()),
+    };
+    let Some(member) = members.iter().find(|member| member.name == name) else {
+        return Content::from(format!("{} not found", name.display()));
+    };
+    if member.is_dir {
+        let children = members
+            .iter()
+            .filter(|child| child.name.parent() == Some(name))
+            .map(|child| {
+                let file_name = child.name.file_name().unwrap_or_default();
+                (!child.is_dir, file_name.to_string_lossy().into_owned())
+            })
+            .collect();
+        let lines = listing(children, max_lines, true);
+        return Content {
+            lines: lines.into_iter().map(Spans::from).collect(),
+            hex_size: None,
+        };
+    }
+    let header = match archive::read(archive, name, MAX_BYTES) {
+        Ok(header) => header,
+        Err(error) => return Content::from(error.to_string()),
+    };
+    let mime = opener::sniff(&header);
+    if opener::is_text(mime) {
+        return text(&member.path(), &header, max_lines, theme);
+    }
+    let mut lines = vec![summary(mime, member.size)];
+    let rows = max_lines.saturating_sub(1) * HEX_ROW;
+    lines.extend(hex_lines(&header[..header.len().min(rows)], 0));
+    Content {
+        lines,
+        hex_size: None,
+    }
+}
+
+/// Highlights the start of a text file and numbers its lines.
+fn text(path: &Path, header: &[u8], max_lines: usize, theme: &str) -> Content {
+    let text = String::from_utf8_lossy(header);
+    let text: String = text.split_inclusive('\n').take(max_lines).collect();
+    let lines = highlight::highlight(path, &text, theme).unwrap_or_else(|| {
+        text.lines()
+            .map(|line| vec![Span::raw(line.replace('\t', "    ").replace('\r', ""))])
+            .collect()
+    });
+    Content {
+        lines: numbered(lines),
+        hex_size: None,
+    }
+}
+
+/// Describes a binary file above its hex dump.
+fn summary(mime: &str, size: u64) -> Spans<'static> {
+    let description = DESCRIPTIONS
+        .iter()
+        .find(|(name, _)| *name == mime)
+        .map_or("binary data", |(_, description)| description);
+    Spans::from(format!("{}, {}", description, format_size(size)))
+}
+
+/// Reads rows of a hex dump from an offset of a file without reading the rest of it.
+fn hex_dump(
+    vfs: &dyn Vfs,
+    path: &Path,
+    offset: u64,
+    rows: usize,
+) -> io::Result<Vec<Spans<'static>>> {
+    let bytes = vfs.read(path, offset, (rows * HEX_ROW) as u64)?;
+    Ok(hex_lines(&bytes, offset))
+}
+
+/// Formats bytes read from an offset as rows of a hex dump.
+fn hex_lines(bytes: &[u8], offset: u64) -> Vec<Spans<'static>> {
+    let style = Style::default().fg(Color::DarkGray);
+    bytes
+        .chunks(HEX_ROW)
+        .enumerate()
+        .map(|(index, row)| {
+            let offset = offset + (index * HEX_ROW) as u64;
+            Spans::from(vec![
+                Span::styled(format!("{:08x}  ", offset), style),
+                Span::raw(hex_row(row)),
+            ])
+        })
+        .collect()
+}
+
+/// Formats a row of bytes as hex in two groups of eight followed by the printable characters
+/// like `hexdump -C`.
+fn hex_row(row: &[u8]) -> String {
+    let mut hex = String::new();
+    for index in 0..HEX_ROW {
+        match row.get(index) {
+            Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+            None => hex.push_str("   "),
+        }
+        if index == HEX_ROW / 2 - 1 {
+            hex.push(' ');
+        }
+    }
+    let ascii: String = row
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{} |{}|", hex, ascii)
+}
+
+/// Prefixes lines with their line numbers.
+fn numbered(lines: Vec<Vec<Span<'static>>>) -> Vec<Spans<'static>> {
+    let width = lines.len().to_string().len();
+    let style = Style::default().fg(Color::DarkGray);
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut spans)| {
+            spans.insert(0, Span::styled(format!("{:>width$} ", index + 1), style));
+            Spans::from(spans)
+        })
+        .collect()
+}
+
+/// Formats a size in bytes with a binary unit e.g. `1.5 KiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+    use crate::vfs::{Local, Memory};
+
+    fn text(content: Content) -> Vec<String> {
+        content
+            .lines
+            .iter()
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), "one\n\ttwo\nthree\n").unwrap();
+        fs::write(dir.join("b.bin"), [0, 1, 2, 3]).unwrap();
+        symlink("a.txt", dir.join("link")).unwrap();
+        symlink("missing", dir.join("broken")).unwrap();
+
+        assert_eq!(
+            text(render(&Local, &dir.join("a.txt"), 2, "", 0)),
+            vec!["1 one", "2     two"]
+        );
+        assert_eq!(
+            text(render(&Local, &dir.join("b.bin"), 10, "", 0)),
+            vec![
+                "binary data, 4 B",
+                "00000000  00 01 02 03                                       |....|"
+            ]
+        );
+        assert_eq!(
+            text(render(&Local, &dir.join("link"), 3, "", 0)),
+            vec!["-> a.txt", "", "1 one"]
+        );
+        assert_eq!(
+            text(render(&Local, &dir.join("broken"), 10, "", 0)),
+            vec!["-> missing", "broken link"]
+        );
+        assert_eq!(
+            text(render(&Local, dir, 10, "", 0)),
+            vec!["sub/", "a.txt", "b.bin", "broken", "link"]
+        );
+        // Only the first entries of the directory are read.
+        let lines = text(render(&Local, dir, 3, "", 0));
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "... more");
+        let socket = std::os::unix::net::UnixListener::bind(dir.join("socket")).unwrap();
+        assert_eq!(
+            text(render(&Local, &dir.join("socket"), 10, "", 0)),
+            vec!["socket"]
+        );
+        drop(socket);
+        assert_eq!(
+            text(render(&Local, &dir.join("sub"), 10, "", 0)),
+            vec!["empty directory"]
+        );
+    }
+
+    #[test]
+    fn test_render_vfs() {
+        let vfs = Memory::default();
+        vfs.insert("/a/b/c.txt", Some(b"one\n"));
+        vfs.insert("/a/d.bin", Some(&[0, 1]));
+        assert_eq!(
+            text(render(&vfs, Path::new("/a"), 10, "", 0)),
+            vec!["b/", "d.bin"]
+        );
+        assert_eq!(
+            text(render(&vfs, Path::new("/a/b/c.txt"), 10, "", 0)),
+            vec!["1 one"]
+        );
+        assert_eq!(
+            text(render(&vfs, Path::new("/a/d.bin"), 10, "", 0)),
+            vec![
+                "binary data, 2 B",
+                "00000000  00 01                                             |..|"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_member() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("member.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("docs/a.txt", options).unwrap();
+        std::io::Write::write_all(&mut zip, b"one\ntwo\n").unwrap();
+        zip.finish().unwrap();
+        assert_eq!(
+            text(render(&Local, &path.join("docs"), 10, "", 0)),
+            vec!["a.txt"]
+        );
+        assert_eq!(
+            text(render(&Local, &path.join("docs/a.txt"), 10, "", 0)),
+            vec!["1 one", "2 two"]
+        );
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("hex");
+        let mut bytes = b"\x7fELF".to_vec();
+        bytes.extend(0..36);
+        fs::write(&path, &bytes).unwrap();
+        let content = render(&Local, &path, 3, "", 16);
+        assert_eq!(content.hex_size, Some(40));
+        assert_eq!(
+            text(content),
+            vec![
+                "ELF executable, 40 B",
+                "00000010  0c 0d 0e 0f 10 11 12 13  14 15 16 17 18 19 1a 1b  |................|",
+                "00000020  1c 1d 1e 1f 20 21 22 23                           |.... !\"#|",
+            ]
+        );
+        assert_eq!(last_page(40, 2), 16);
+        assert_eq!(last_page(40, 5), 0);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(10), "10 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Session saved when quitting, restored when `fm` is started in the same root again.
+pub const LAST_SESSION: &str = "last";
+
+/// Layout of the tree saved to `<name>.toml` in the sessions directory.
+/// Paths other than the root are relative to it, marks outside of the root are not saved.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Session {
+    /// Shared path of the root, a URL for a remote one.
+    pub root: PathBuf,
+    pub expanded: Vec<PathBuf>,
+    pub selected: Option<PathBuf>,
+    pub marked: Vec<PathBuf>,
+    /// Index of the first visible file.
+    pub offset: usize,
+}
+
+impl Session {
+    pub fn load(dir: &Path, name: &str) -> Result<Self, Error> {
+        let path = session_path(dir, name)?;
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("could not read session {}", name))?;
+        toml::from_str(&contents).with_context(|| format!("invalid session {}", name))
+    }
+
+    /// Writes the session to a temporary file first so a session is never left half written.
+    pub fn save(&self, dir: &Path, name: &str) -> Result<(), Error> {
+        let path = session_path(dir, name)?;
+        fs::create_dir_all(dir)?;
+        let temporary = path.with_extension("toml.tmp");
+        fs::write(&temporary, toml::to_string(self)?)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+}
+
+/// Session names are file names without a path or a leading dot.
+fn session_path(dir: &Path, name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(anyhow!("invalid session name: {}", name));
+    }
+    Ok(dir.join(format!("{}.toml", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let session = Session {
+            root: PathBuf::from("sftp://user@host/srv"),
+            expanded: vec![PathBuf::from("src"), PathBuf::from("src/bin")],
+            selected: Some(PathBuf::from("src/main.rs")),
+            marked: vec![PathBuf::from("Cargo.toml")],
+            offset: 12,
+        };
+        session.save(dir, "work").unwrap();
+        assert_eq!(Session::load(dir, "work").unwrap(), session);
+        assert!(Session::load(dir, "missing").is_err());
+        assert!(session.save(dir, "../work").is_err());
+        assert!(session.save(dir, ".hidden").is_err());
+        assert!(session.save(dir, "").is_err());
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Error, Result};
+use ssh2::{CheckResult, ErrorCode, FileStat, KnownHostFileKind, Session};
+
+use crate::commands::shell_quote;
+use crate::vfs::{Stat, Vfs};
+
+/// Prefix of the locations browsed over SFTP e.g. `sftp://user@host:2222/srv/www`.
+pub const SCHEME: &str = "sftp://";
+
+const DEFAULT_PORT: u16 = 22;
+
+/// Private keys in `~/.ssh` tried in order when the ssh agent can't authenticate.
+const KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Time after which connecting or waiting for the host fails, so a lost connection doesn't
+/// block the interface.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `LIBSSH2_FX_PERMISSION_DENIED`, which the `ssh2` crate doesn't map to an `io::ErrorKind`.
+const FX_PERMISSION_DENIED: i32 = 3;
+
+/// Open connections by address, shared by every path on the same host.
+static CONNECTIONS: OnceLock<Mutex<HashMap<Address, Arc<Remote>>>> = OnceLock::new();
+
+/// User, host and port of a remote filesystem.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Address {
+    pub user: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Address {
+    /// Returns the URL of a path on the host, the port is left out when it is the default.
+    pub fn url(&self, path: &Path) -> String {
+        let port = if self.port == DEFAULT_PORT {
+            String::new()
+        } else {
+            format!(":{}", self.port)
+        };
+        format!(
+            "{}{}@{}{}{}",
+            SCHEME,
+            self.user,
+            self.host,
+            port,
+            path.display()
+        )
+    }
+}
+
+/// Splits an `sftp://[user@]host[:port][/path]` URL into the address and the remote path.
+/// The user defaults to the local user and an empty path is the home directory on the host.
+pub fn parse(url: &str) -> Option<(Address, PathBuf)> {
+    let rest = url.strip_prefix(SCHEME)?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let (user, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => (user.to_owned(), host),
+        None => (whoami::username(), authority),
+    };
+    let (host, port) = match host.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (host, DEFAULT_PORT),
+    };
+    if host.is_empty() || user.is_empty() {
+        return None;
+    }
+    let address = Address {
+        user,
+        host: host.to_owned(),
+        port,
+    };
+    Some((address, PathBuf::from(path)))
+}
+
+/// Returns the connection to a host, connecting when there is none yet.
+pub fn connect(address: &Address) -> Result<Arc<Remote>, Error> {
+    let connections = CONNECTIONS.get_or_init(Mutex::default);
+    let mut connections = connections
+        .lock()
+        .map_err(|_| anyhow!("connection pool poisoned"))?;
+    if let Some(remote) = connections.get(address) {
+        return Ok(Arc::clone(remote));
+    }
+    let remote = Arc::new(Remote::connect(address.clone())?);
+    connections.insert(address.clone(), Arc::clone(&remote));
+    Ok(remote)
+}
+
+/// A filesystem on another host browsed over SFTP on a single SSH connection.
+/// A connection which was lost is replaced by a new one on the next operation.
+pub struct Remote {
+    address: Address,
+    connection: Mutex<Option<Arc<Connection>>>,
+}
+
+struct Connection {
+    session: Session,
+    sftp: ssh2::Sftp,
+}
+
+impl Connection {
+    fn open(address: &Address) -> Result<Self, Error> {
+        let error = |error: io::Error| anyhow!("{}: {}", address.host, error);
+        let socket = (address.host.as_str(), address.port)
+            .to_socket_addrs()
+            .map_err(error)?
+            .next()
+            .ok_or_else(|| anyhow!("{}: no address", address.host))?;
+        let tcp = TcpStream::connect_timeout(&socket, TIMEOUT).map_err(error)?;
+        let mut session = Session::new()?;
+        session.set_timeout(TIMEOUT.as_millis() as u32);
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+        check_host_key(&session, address)?;
+        authenticate(&session, &address.user)?;
+        let sftp = session.sftp()?;
+        Ok(Connection { session, sftp })
+    }
+}
+
+impl Remote {
+    fn connect(address: Address) -> Result<Self, Error> {
+        let connection = Connection::open(&address)?;
+        Ok(Remote {
+            address,
+            connection: Mutex::new(Some(Arc::new(connection))),
+        })
+    }
+
+    /// Runs an operation on the connection, connecting again first if it was lost. Session
+    /// errors, which include socket errors and timeouts, drop the connection.
+    fn with<T>(
+        &self,
+        operation: impl FnOnce(&Connection) -> Result<T, ssh2::Error>,
+    ) -> io::Result<T> {
+        let connection = {
+            let mut current = self
+                .connection
+                .lock()
+                .map_err(|_| io::Error::other("connection poisoned"))?;
+            match &*current {
+                Some(connection) => Arc::clone(connection),
+                None => {
+                    let connection = Connection::open(&self.address)
+                        .map_err(|error| io::Error::other(error.to_string()))?;
+                    Arc::clone(current.insert(Arc::new(connection)))
+                }
+            }
+        };
+        operation(&connection).map_err(|error| {
+            if matches!(error.code(), ErrorCode::Session(_)) {
+                if let Ok(mut current) = self.connection.lock() {
+                    // Another thread may have connected again in the meantime.
+                    if current
+                        .as_ref()
+                        .is_some_and(|current| Arc::ptr_eq(current, &connection))
+                    {
+                        *current = None;
+                    }
+                }
+            }
+            io_error(error)
+        })
+    }
+
+    /// Resolves a path relative to the home directory on the host.
+    pub fn absolute(&self, path: &Path) -> Result<PathBuf, Error> {
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+        let home = self.with(|connection| connection.sftp.realpath(Path::new(".")))?;
+        Ok(home.join(path))
+    }
+
+    /// Runs a command on the host and fails with its error output unless it succeeds.
+    fn exec(&self, command: &str) -> io::Result<()> {
+        let mut channel = self.with(|connection| connection.session.channel_session())?;
+        channel.exec(command).map_err(io_error)?;
+        let mut errors = String::new();
+        channel.stderr().read_to_string(&mut errors)?;
+        channel.wait_close().map_err(io_error)?;
+        match channel.exit_status().map_err(io_error)? {
+            0 => Ok(()),
+            _ => Err(io::Error::other(errors.trim().to_owned())),
+        }
+    }
+}
+
+impl Vfs for Remote {
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let entries = self.with(|connection| connection.sftp.readdir(dir))?;
+        Ok(entries.into_iter().map(|(path, _)| path).collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let is_symlink = self
+            .with(|connection| connection.sftp.lstat(path))?
+            .file_type()
+            .is_symlink();
+        let stat = self.with(|connection| connection.sftp.stat(path))?;
+        Ok(Stat {
+            is_symlink,
+            ..to_stat(&stat)
+        })
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.with(|connection| connection.sftp.readlink(path))
+    }
+
+    fn read(&self, path: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut file = self.with(|connection| connection.sftp.open(path))?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![];
+        file.take(len).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(
+            self.with(|connection| connection.sftp.open(path))?,
+        ))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(
+            self.with(|connection| connection.sftp.create(path))?,
+        ))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.with(|connection| connection.sftp.mkdir(path, 0o755))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.with(|connection| connection.sftp.rename(from, to, None))
+    }
+
+    /// SFTP can't copy on the server so `cp` is run there instead of sending the files back
+    /// and forth.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let quote = |path: &Path| shell_quote(&path.to_string_lossy());
+        self.exec(&format!("cp -R -- {} {}", quote(from), quote(to)))
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let stat = self.with(|connection| connection.sftp.lstat(path))?;
+        if !stat.is_dir() {
+            return self.with(|connection| connection.sftp.unlink(path));
+        }
+        for child in self.list(path)? {
+            self.remove(&child)?;
+        }
+        self.with(|connection| connection.sftp.rmdir(path))
+    }
+
+    fn url(&self, path: &Path) -> Option<String> {
+        Some(self.address.url(path))
+    }
+}
+
+/// Accepts the host only if its key is in `~/.ssh/known_hosts`, a new host has to be connected
+/// to once with `ssh` to confirm its key.
+fn check_host_key(session: &Session, address: &Address) -> Result<(), Error> {
+    let mut known_hosts = session.known_hosts()?;
+    let file = dirs::home_dir()
+        .unwrap_or_default()
+        .join(".ssh/known_hosts");
+    if file.is_file() {
+        known_hosts.read_file(&file, KnownHostFileKind::OpenSSH)?;
+    }
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| anyhow!("{}: no host key", address.host))?;
+    match known_hosts.check_port(&address.host, address.port, key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => bail!("{}: the host key has changed", address.host),
+        CheckResult::NotFound => bail!(
+            "{}: unknown host key, connect once with ssh to add it",
+            address.host
+        ),
+        CheckResult::Failure => bail!("{}: could not check the host key", address.host),
+    }
+}
+
+/// Authenticates with the ssh agent or else with the first key in `~/.ssh` that is accepted.
+fn authenticate(session: &Session, user: &str) -> Result<(), Error> {
+    if session.userauth_agent(user).is_ok() && session.authenticated() {
+        return Ok(());
+    }
+    let dir = dirs::home_dir().unwrap_or_default().join(".ssh");
+    for key in KEYS {
+        let key = dir.join(key);
+        if key.is_file() && session.userauth_pubkey_file(user, None, &key, None).is_ok() {
+            return Ok(());
+        }
+    }
+    bail!(
+        "could not authenticate as {} with the ssh agent or a key in ~/.ssh",
+        user
+    )
+}
+
+fn to_stat(stat: &FileStat) -> Stat {
+    Stat {
+        is_dir: stat.is_dir(),
+        is_file: stat.is_file(),
+        is_symlink: false,
+        len: stat.size.unwrap_or(0),
+        mode: stat.perm.unwrap_or(0),
+        modified: stat
+            .mtime
+            .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime)),
+        // SFTP version 3 has no status change time.
+        changed: None,
+    }
+}
+
+/// Converts an error of the `ssh2` crate, keeping missing files and denied permissions apart.
+fn io_error(error: ssh2::Error) -> io::Error {
+    match error.code() {
+        ErrorCode::SFTP(FX_PERMISSION_DENIED) => {
+            io::Error::new(io::ErrorKind::PermissionDenied, error.message().to_owned())
+        }
+        _ => error.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::vfs;
+
+    #[test]
+    fn test_parse() {
+        let address = |user: &str, host: &str, port| Address {
+            user: user.into(),
+            host: host.into(),
+            port,
+        };
+        assert_eq!(
+            parse("sftp://me@build:2222/srv/www"),
+            Some((address("me", "build", 2222), PathBuf::from("/srv/www")))
+        );
+        assert_eq!(
+            parse("sftp://me@build"),
+            Some((address("me", "build", 22), PathBuf::new()))
+        );
+        let (local, path) = parse("sftp://build/").unwrap();
+        assert_eq!(local.user, whoami::username());
+        assert_eq!(path, Path::new("/"));
+        assert_eq!(parse("sftp://me@/srv"), None);
+        assert_eq!(parse("sftp://build:ssh/srv"), None);
+        assert_eq!(parse("/home/me"), None);
+        assert_eq!(
+            address("me", "build", 2222).url(Path::new("/a b")),
+            "sftp://me@build:2222/a b"
+        );
+        assert_eq!(
+            address("me", "build", 22).url(Path::new("/a")),
+            "sftp://me@build/a"
+        );
+    }
+
+    /// Needs an sshd which accepts the local user's agent or keys, e.g. run with
+    /// `FM_TEST_SFTP=sftp://localhost/tmp cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_remote() {
+        let url = env::var("FM_TEST_SFTP").expect("FM_TEST_SFTP is not set");
+        let (remote, dir) = vfs::resolve(Path::new(&url)).unwrap();
+        let (again, _) = vfs::resolve(Path::new(&url)).unwrap();
+        assert!(Arc::ptr_eq(&remote, &again), "the connection is reused");
+
+        let dir = dir.join(format!("fm-test-sftp-{}", std::process::id()));
+        remote.create_dir(&dir).unwrap();
+        remote
+            .create(&dir.join("a.txt"))
+            .unwrap()
+            .write_all(b"hello")
+            .unwrap();
+        assert_eq!(remote.list(&dir).unwrap(), vec![dir.join("a.txt")]);
+        assert_eq!(remote.stat(&dir.join("a.txt")).unwrap().len, 5);
+        assert_eq!(remote.read(&dir.join("a.txt"), 1, 3).unwrap(), b"ell");
+        assert_eq!(
+            remote.stat(&dir.join("missing")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        remote.copy(&dir.join("a.txt"), &dir.join("b.txt")).unwrap();
+        remote
+            .rename(&dir.join("b.txt"), &dir.join("c.txt"))
+            .unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let local = temp.path().join("local");
+        vfs::transfer(remote.as_ref(), &dir, &vfs::Local, &local).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(local.join("c.txt")).unwrap(),
+            "hello"
+        );
+        vfs::transfer(&vfs::Local, &local, remote.as_ref(), &dir.join("up")).unwrap();
+        assert!(remote.stat(&dir.join("up/a.txt")).unwrap().is_file);
+
+        remote.remove(&dir).unwrap();
+        assert!(remote.stat(&dir).is_err());
+    }
+}
@@ -1,5 +1,105 @@
 use super::files;
 use super::*;
+use config::{Sort, SortKey};
 
 #[test]
 fn test_files() {}
+
+#[test]
+fn test_expand_braces() {
+    assert_eq!(files::expand_braces("main.rs"), vec!["main.rs"]);
+    assert_eq!(
+        files::expand_braces("src/{lib,main}.rs"),
+        vec!["src/lib.rs", "src/main.rs"]
+    );
+    assert_eq!(
+        files::expand_braces("{a,b{1,2}}/{x,y}"),
+        vec!["a/x", "a/y", "b1/x", "b1/y", "b2/x", "b2/y"]
+    );
+    assert_eq!(files::expand_braces("{a}.rs"), vec!["{a}.rs"]);
+    assert_eq!(files::expand_braces("{a,b"), vec!["{a,b"]);
+}
+
+#[test]
+fn test_read_dir() {
+    let vfs = vfs::Memory::default();
+    vfs.insert("/root/src/main.rs", Some(b"fn main() {}\n"));
+    vfs.insert("/root/README", Some(b"hello\n"));
+    vfs.insert("/root/.git", None);
+    let sort = Sort::default();
+    let root = Application::read_dir(&vfs, PathBuf::from("/root"), false, &sort).unwrap();
+    let names: Vec<_> = root.descendants.iter().map(|file| &file.path).collect();
+    assert_eq!(
+        names,
+        vec![Path::new("/root/src"), Path::new("/root/README")]
+    );
+    assert!(root.descendants[0].is_dir());
+    assert_eq!(root.descendants[1].stat.len, 6);
+    let root = Application::read_dir(&vfs, PathBuf::from("/root"), true, &sort).unwrap();
+    assert_eq!(root.descendants.len(), 3);
+    assert!(Application::read_dir(&vfs, PathBuf::from("/missing"), false, &sort).is_err());
+}
+
+#[test]
+fn test_natural_cmp() {
+    use std::cmp::Ordering;
+    assert_eq!(files::natural_cmp("file2", "file10"), Ordering::Less);
+    assert_eq!(files::natural_cmp("file10", "file9"), Ordering::Greater);
+    assert_eq!(files::natural_cmp("v1.10.2", "v1.9.12"), Ordering::Greater);
+    assert_eq!(files::natural_cmp("file1", "file01"), Ordering::Less);
+    assert_eq!(files::natural_cmp("a", "a1"), Ordering::Less);
+    assert_eq!(files::natural_cmp("b", "a2"), Ordering::Greater);
+    assert_eq!(files::natural_cmp("file", "file"), Ordering::Equal);
+}
+
+#[test]
+fn test_sort() {
+    let vfs = vfs::Memory::default();
+    vfs.insert("/root/file10.rs", Some(b"0123456789"));
+    vfs.insert("/root/file2.txt", Some(b"01"));
+    vfs.insert("/root/File3", Some(b"012"));
+    vfs.insert("/root/dir", None);
+    let names = |sort: &Sort| {
+        let root = Application::read_dir(&vfs, PathBuf::from("/root"), false, sort).unwrap();
+        root.descendants
+            .iter()
+            .map(|file| {
+                file.path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut sort = Sort::default();
+    assert_eq!(names(&sort), ["dir", "file2.txt", "File3", "file10.rs"]);
+    sort.by = SortKey::Name;
+    assert_eq!(names(&sort), ["dir", "file10.rs", "file2.txt", "File3"]);
+    sort.case_sensitive = true;
+    assert_eq!(names(&sort), ["dir", "File3", "file10.rs", "file2.txt"]);
+    sort.by = SortKey::Size;
+    assert_eq!(names(&sort), ["dir", "file10.rs", "File3", "file2.txt"]);
+    sort.reverse = true;
+    assert_eq!(names(&sort), ["dir", "file2.txt", "File3", "file10.rs"]);
+    sort.by = SortKey::Extension;
+    sort.reverse = false;
+    sort.directories_first = false;
+    assert_eq!(names(&sort), ["File3", "dir", "file10.rs", "file2.txt"]);
+}
+
+#[test]
+fn test_new_path() {
+    let parent = Path::new("/project");
+    assert_eq!(
+        Application::new_path(parent, "src/./main.rs").unwrap(),
+        Path::new("/project/src/main.rs")
+    );
+    assert_eq!(
+        Application::new_path(parent, "docs/").unwrap(),
+        Path::new("/project/docs")
+    );
+    assert!(Application::new_path(parent, "/etc/passwd").is_err());
+    assert!(Application::new_path(parent, "src/../../escaped").is_err());
+    assert!(Application::new_path(parent, "").is_err());
+}
@@ -0,0 +1,455 @@
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Error, Result};
+use walkdir::WalkDir;
+
+use crate::sftp;
+
+/// Metadata of a file as far as the tree and the preview need it. Symbolic links are followed,
+/// `is_symlink` tells whether the path itself is a link.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub is_dir: bool,
+    pub is_file: bool,
+    pub is_symlink: bool,
+    pub len: u64,
+    /// Unix mode including the file type bits, 0 when the backend doesn't know it.
+    pub mode: u32,
+    pub modified: Option<SystemTime>,
+    /// Time of the last status change, `None` when the backend doesn't know it.
+    pub changed: Option<SystemTime>,
+}
+
+impl Stat {
+    pub fn is_executable(&self) -> bool {
+        self.mode & 0o111 != 0
+    }
+}
+
+/// A filesystem the tree and the preview are read from, so other backends than the local disk
+/// can be browsed. Errors are `io::Error`s so callers can tell missing files and denied
+/// permissions apart whatever the backend.
+pub trait Vfs: Send + Sync {
+    /// Returns the paths of the entries of a directory in no particular order.
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Returns the paths of at most `limit` entries of a directory, without reading the others
+    /// where the backend allows it.
+    fn list_limit(&self, dir: &Path, limit: usize) -> io::Result<Vec<PathBuf>> {
+        let mut entries = self.list(dir)?;
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat>;
+
+    /// Returns the target of a symbolic link.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Reads up to `len` bytes of a file starting at `offset`.
+    fn read(&self, path: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>>;
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Creates a file, or truncates it if it exists, for writing.
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies a file, or a directory with everything below it.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes a file, or a directory with everything below it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Returns the URL a path is shared as with other instances e.g. when it is copied, `None`
+    /// for paths of the local filesystem which are shared as they are.
+    fn url(&self, path: &Path) -> Option<String> {
+        None
+    }
+}
+
+/// Returns the filesystem of a shared path and the path on it, connecting to the host of an
+/// `sftp://` URL or reusing its connection.
+pub fn resolve(path: &Path) -> Result<(Arc<dyn Vfs>, PathBuf), Error> {
+    match sftp::parse(&path.to_string_lossy()) {
+        Some((address, path)) => {
+            let remote = sftp::connect(&address)?;
+            let path = remote.absolute(&path)?;
+            Ok((remote, path))
+        }
+        None => Ok((Arc::new(Local), path.to_path_buf())),
+    }
+}
+
+/// Creates a directory along with any missing parent directories.
+pub fn create_dir_all(vfs: &dyn Vfs, path: &Path) -> io::Result<()> {
+    if vfs.stat(path).is_ok_and(|stat| stat.is_dir) {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        create_dir_all(vfs, parent)?;
+    }
+    vfs.create_dir(path)
+}
+
+/// Copies a file, or a directory with everything below it, from one filesystem to another.
+/// Links are followed and an existing destination is never overwritten.
+pub fn transfer(from_vfs: &dyn Vfs, from: &Path, to_vfs: &dyn Vfs, to: &Path) -> io::Result<()> {
+    if to_vfs.stat(to).is_ok() {
+        let message = format!("{} already exists", to.display());
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, message));
+    }
+    if from_vfs.stat(from)?.is_dir {
+        to_vfs.create_dir(to)?;
+        for path in from_vfs.list(from)? {
+            if let Some(name) = path.file_name() {
+                transfer(from_vfs, &path, to_vfs, &to.join(name))?;
+            }
+        }
+        return Ok(());
+    }
+    io::copy(&mut from_vfs.open(from)?, &mut to_vfs.create(to)?)?;
+    Ok(())
+}
+
+/// The local filesystem.
+pub struct Local;
+
+impl Vfs for Local {
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn list_limit(&self, dir: &Path, limit: usize) -> io::Result<Vec<PathBuf>> {
+        fs::read_dir(dir)?
+            .take(limit)
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        let metadata = fs::metadata(path)?;
+        let is_symlink = fs::symlink_metadata(path)?.is_symlink();
+        Ok(Stat {
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            is_symlink,
+            len: metadata.len(),
+            mode: metadata.permissions().mode(),
+            modified: metadata.modified().ok(),
+            changed: UNIX_EPOCH.checked_add(Duration::new(
+                metadata.ctime() as u64,
+                metadata.ctime_nsec() as u32,
+            )),
+        })
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn read(&self, path: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![];
+        file.take(len).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+
+    fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        Ok(Box::new(fs::File::create(path)?))
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        for entry in WalkDir::new(from) {
+            let entry = entry?;
+            let relative = entry.path().strip_prefix(from).unwrap_or(Path::new(""));
+            let target = if relative.as_os_str().is_empty() {
+                to.to_path_buf()
+            } else {
+                to.join(relative)
+            };
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&target)?;
+            } else if entry.file_type().is_symlink() {
+                std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+            } else {
+                fs::copy(entry.path(), &target)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+}
+
+#[cfg(test)]
+pub use memory::Memory;
+
+#[cfg(test)]
+mod memory {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use super::*;
+
+    type Files = Arc<Mutex<BTreeMap<PathBuf, Option<Vec<u8>>>>>;
+
+    /// A filesystem kept in memory for tests, directories are created implicitly.
+    #[derive(Default)]
+    pub struct Memory {
+        files: Files,
+    }
+
+    /// A file of a `Memory` filesystem open for writing.
+    struct MemoryFile {
+        files: Files,
+        path: PathBuf,
+    }
+
+    impl Write for MemoryFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut files = self.files.lock().unwrap();
+            if let Some(Some(bytes)) = files.get_mut(&self.path) {
+                bytes.extend_from_slice(buf);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Memory {
+        /// Adds a file and its parent directories, a `None` contents adds a directory.
+        pub fn insert(&self, path: &str, contents: Option<&[u8]>) {
+            let path = PathBuf::from(path);
+            let mut files = self.files.lock().unwrap();
+            for ancestor in path.ancestors().skip(1) {
+                files.entry(ancestor.to_path_buf()).or_insert(None);
+            }
+            files.insert(path, contents.map(<[u8]>::to_vec));
+        }
+
+        fn not_found(path: &Path) -> io::Error {
+            io::Error::new(io::ErrorKind::NotFound, path.display().to_string())
+        }
+
+        fn create_parent(&self, path: &Path) -> io::Result<()> {
+            let parent = path.parent().unwrap_or(Path::new("/"));
+            match self.stat(parent)? {
+                stat if stat.is_dir => Ok(()),
+                _ => Err(io::Error::from(io::ErrorKind::NotADirectory)),
+            }
+        }
+    }
+
+    impl Vfs for Memory {
+        fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+            let files = self.files.lock().unwrap();
+            match files.get(dir) {
+                Some(None) => Ok(files
+                    .keys()
+                    .filter(|path| path.parent() == Some(dir))
+                    .cloned()
+                    .collect()),
+                Some(Some(_)) => Err(io::Error::from(io::ErrorKind::NotADirectory)),
+                None => Err(Self::not_found(dir)),
+            }
+        }
+
+        fn stat(&self, path: &Path) -> io::Result<Stat> {
+            let files = self.files.lock().unwrap();
+            let contents = files.get(path).ok_or_else(|| Self::not_found(path))?;
+            Ok(Stat {
+                is_dir: contents.is_none(),
+                is_file: contents.is_some(),
+                len: contents.as_ref().map_or(0, |bytes| bytes.len() as u64),
+                mode: 0o644,
+                ..Stat::default()
+            })
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "not a link"))
+        }
+
+        fn read(&self, path: &Path, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+            let files = self.files.lock().unwrap();
+            match files.get(path) {
+                Some(Some(bytes)) => Ok(bytes
+                    .iter()
+                    .skip(offset as usize)
+                    .take(len as usize)
+                    .copied()
+                    .collect()),
+                Some(None) => Err(io::Error::from(io::ErrorKind::IsADirectory)),
+                None => Err(Self::not_found(path)),
+            }
+        }
+
+        fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+            let bytes = self.read(path, 0, u64::MAX)?;
+            Ok(Box::new(io::Cursor::new(bytes)))
+        }
+
+        fn create(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+            self.create_parent(path)?;
+            let mut files = self.files.lock().unwrap();
+            files.insert(path.to_path_buf(), Some(vec![]));
+            Ok(Box::new(MemoryFile {
+                files: Arc::clone(&self.files),
+                path: path.to_path_buf(),
+            }))
+        }
+
+        fn create_dir(&self, path: &Path) -> io::Result<()> {
+            self.create_parent(path)?;
+            let mut files = self.files.lock().unwrap();
+            if files.contains_key(path) {
+                return Err(io::Error::from(io::ErrorKind::AlreadyExists));
+            }
+            files.insert(path.to_path_buf(), None);
+            Ok(())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.copy(from, to)?;
+            self.remove(from)
+        }
+
+        fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            if !files.contains_key(from) {
+                return Err(Self::not_found(from));
+            }
+            let copies: Vec<_> = files
+                .iter()
+                .filter_map(|(path, contents)| {
+                    let relative = path.strip_prefix(from).ok()?;
+                    let target = if relative.as_os_str().is_empty() {
+                        to.to_path_buf()
+                    } else {
+                        to.join(relative)
+                    };
+                    Some((target, contents.clone()))
+                })
+                .collect();
+            files.extend(copies);
+            Ok(())
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            if !files.contains_key(path) {
+                return Err(Self::not_found(path));
+            }
+            files.retain(|file, _| !file.starts_with(path));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("a/b")).unwrap();
+        fs::write(dir.join("a/b/c.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink("b/c.txt", dir.join("a/link")).unwrap();
+
+        let mut names = Local.list(&dir.join("a")).unwrap();
+        names.sort();
+        assert_eq!(names, vec![dir.join("a/b"), dir.join("a/link")]);
+        let stat = Local.stat(&dir.join("a/link")).unwrap();
+        assert!(stat.is_file && stat.is_symlink && stat.len == 5);
+        assert_eq!(
+            Local.read_link(&dir.join("a/link")).unwrap(),
+            Path::new("b/c.txt")
+        );
+        assert_eq!(Local.read(&dir.join("a/b/c.txt"), 1, 3).unwrap(), b"ell");
+
+        Local.copy(&dir.join("a"), &dir.join("copy")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("copy/link")).unwrap(), "hello");
+        Local.rename(&dir.join("copy"), &dir.join("moved")).unwrap();
+        assert!(!dir.join("copy").exists());
+        Local.remove(&dir.join("moved")).unwrap();
+        Local.remove(&dir.join("a/link")).unwrap();
+        assert!(dir.join("a/b/c.txt").exists());
+    }
+
+    #[test]
+    fn test_memory() {
+        let vfs = Memory::default();
+        vfs.insert("/a/b.txt", Some(b"hello"));
+        vfs.insert("/a/c", None);
+        assert_eq!(
+            vfs.list(Path::new("/a")).unwrap(),
+            vec![PathBuf::from("/a/b.txt"), PathBuf::from("/a/c")]
+        );
+        assert!(vfs.stat(Path::new("/a/c")).unwrap().is_dir);
+        assert_eq!(vfs.stat(Path::new("/a/b.txt")).unwrap().len, 5);
+        assert_eq!(vfs.read(Path::new("/a/b.txt"), 3, 10).unwrap(), b"lo");
+        vfs.rename(Path::new("/a"), Path::new("/d")).unwrap();
+        assert_eq!(
+            vfs.stat(Path::new("/a")).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert_eq!(vfs.read(Path::new("/d/b.txt"), 0, 10).unwrap(), b"hello");
+        create_dir_all(&vfs, Path::new("/d/e/f")).unwrap();
+        create_dir_all(&vfs, Path::new("/d/e")).unwrap();
+        assert!(vfs.stat(Path::new("/d/e/f")).unwrap().is_dir);
+        assert!(create_dir_all(&vfs, Path::new("/d/b.txt/g")).is_err());
+    }
+
+    #[test]
+    fn test_transfer() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let memory = Memory::default();
+        memory.insert("/a/b/c.txt", Some(b"hello"));
+        memory.insert("/a/d", None);
+
+        transfer(&memory, Path::new("/a"), &Local, &dir.join("a")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a/b/c.txt")).unwrap(), "hello");
+        assert!(dir.join("a/d").is_dir());
+        let error = transfer(&memory, Path::new("/a"), &Local, &dir.join("a")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+
+        transfer(&Local, &dir.join("a/b"), &memory, Path::new("/e")).unwrap();
+        assert_eq!(memory.read(Path::new("/e/c.txt"), 0, 10).unwrap(), b"hello");
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Error, Result};
+use fs4::fs_std::FileExt;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, Sort};
+
+/// How a directory is shown when it is the root.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct View {
+    pub sort: Sort,
+    pub show_hidden: bool,
+    /// Expanded directories relative to the root.
+    pub expanded: Vec<PathBuf>,
+}
+
+impl View {
+    /// Returns the view of a directory nothing is remembered about.
+    pub fn new(config: &Config) -> Self {
+        View {
+            sort: config.sort.clone(),
+            show_hidden: config.show_hidden,
+            expanded: vec![],
+        }
+    }
+}
+
+/// Views remembered per root directory in a TOML file keyed by the path of the directory.
+/// Only views which differ from the configured one are kept.
+#[derive(Default)]
+pub struct Views {
+    path: Option<PathBuf>,
+    views: BTreeMap<String, View>,
+    pub default: View,
+}
+
+impl Views {
+    pub fn load(path: PathBuf, default: View) -> Self {
+        let views = match std::fs::read_to_string(&path) {
+            Ok(contents) => parse(&contents).unwrap_or_else(|error| {
+                error!(
+                    "could not parse views file, it is left untouched: {}",
+                    error
+                );
+                BTreeMap::new()
+            }),
+            Err(error) => {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    error!("could not read views file: {}", error);
+                }
+                BTreeMap::new()
+            }
+        };
+        Views {
+            path: Some(path),
+            views,
+            default,
+        }
+    }
+
+    /// Returns the view remembered for a directory or the default one.
+    pub fn get(&self, dir: &Path) -> View {
+        self.views
+            .get(&*dir.to_string_lossy())
+            .cloned()
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// Remembers the view of a directory and writes it to the views file.
+    /// The file is re-read under a lock so views saved by other instances are kept,
+    /// a file which can't be parsed is never overwritten.
+    pub fn save(&mut self, dir: &Path, view: View) -> Result<(), Error> {
+        let dir = dir.to_string_lossy().into_owned();
+        let view = Some(view).filter(|view| *view != self.default);
+        if self.views.get(&dir) == view.as_ref() {
+            return Ok(());
+        }
+        update(&mut self.views, &dir, view.clone());
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.lock_exclusive()?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut views = parse(&contents)
+            .with_context(|| format!("could not parse {}, it is left untouched", path.display()))?;
+        update(&mut views, &dir, view);
+        file.seek(SeekFrom::Start(0))?;
+        file.set_len(0)?;
+        file.write_all(toml::to_string(&views)?.as_bytes())?;
+        file.sync_all()?;
+        #[allow(unstable_name_collisions)]
+        file.unlock()?;
+        self.views = views;
+        Ok(())
+    }
+}
+
+/// A single view which can't be parsed, e.g. after a sort key was removed, fails the whole file.
+fn parse(contents: &str) -> Result<BTreeMap<String, View>> {
+    Ok(toml::from_str(contents)?)
+}
+
+fn update(views: &mut BTreeMap<String, View>, dir: &str, view: Option<View>) {
+    match view {
+        Some(view) => views.insert(dir.to_owned(), view),
+        None => views.remove(dir),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SortKey;
+
+    #[test]
+    fn test_views() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("views.toml");
+        let mut views = Views::load(path.clone(), View::default());
+        let view = View {
+            sort: Sort {
+                by: SortKey::Mtime,
+                ..Sort::default()
+            },
+            show_hidden: true,
+            expanded: vec![PathBuf::from("src")],
+        };
+        views.save(Path::new("/project"), view.clone()).unwrap();
+        views.save(Path::new("/other"), View::default()).unwrap();
+
+        let mut views = Views::load(path.clone(), View::default());
+        assert_eq!(views.get(Path::new("/project")), view);
+        assert_eq!(views.get(Path::new("/other")), View::default());
+        views.save(Path::new("/project"), View::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        // A file which can't be parsed is kept as is.
+        std::fs::write(&path, "[\"/project\"]\nsort = 1\n").unwrap();
+        let mut views = Views::load(path.clone(), View::default());
+        assert_eq!(views.get(Path::new("/project")), View::default());
+        assert!(views.save(Path::new("/project"), view.clone()).is_err());
+        assert_eq!(views.get(Path::new("/project")), view);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[\"/project\"]\nsort = 1\n"
+        );
+    }
+}